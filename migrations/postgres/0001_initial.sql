@@ -0,0 +1,129 @@
+-- The same tables as the SQLite migrations, with the blobs as JSONB and amounts as NUMERIC.
+
+CREATE TABLE file4s ("id"	TEXT NOT NULL PRIMARY KEY,
+	"file4"	JSONB NOT NULL);
+
+CREATE TABLE cursors ("feed"	TEXT NOT NULL PRIMARY KEY,
+	"last_change"	TEXT NOT NULL);
+
+CREATE TABLE incomplete_polls ("feed"	TEXT NOT NULL,
+	"polled_at"	TEXT NOT NULL,
+	"last_change"	TEXT NOT NULL,
+	"oldest_seen"	TEXT);
+
+CREATE TABLE failed_filings ("url"	TEXT NOT NULL PRIMARY KEY,
+	"accession"	TEXT,
+	"submission"	TEXT NOT NULL,
+	"error"	TEXT NOT NULL,
+	"failed_at"	TEXT NOT NULL);
+
+CREATE INDEX failed_filings_accession ON failed_filings ("accession");
+
+CREATE TABLE retries ("url"	TEXT NOT NULL PRIMARY KEY,
+	"feed"	TEXT NOT NULL,
+	"attempts"	BIGINT NOT NULL,
+	"next_attempt"	TEXT NOT NULL,
+	"error"	TEXT NOT NULL);
+
+CREATE TABLE failed_downloads ("url"	TEXT NOT NULL PRIMARY KEY,
+	"feed"	TEXT NOT NULL,
+	"attempts"	BIGINT NOT NULL,
+	"error"	TEXT NOT NULL,
+	"failed_at"	TEXT NOT NULL);
+
+CREATE TABLE amendments ("id"	TEXT NOT NULL PRIMARY KEY,
+	"amends"	TEXT);
+
+CREATE TABLE file144s ("id"	TEXT NOT NULL PRIMARY KEY,
+	"file144"	JSONB NOT NULL,
+	"file4"	TEXT);
+
+CREATE TABLE schedule13s ("id"	TEXT NOT NULL PRIMARY KEY,
+	"schedule13"	JSONB NOT NULL);
+
+CREATE VIEW issuer_filings AS
+	SELECT file4->'issuer'->>'cik' AS "issuer_cik", "id", 'file4' AS "kind", "file4" AS "data" FROM file4s
+	UNION ALL SELECT file144->>'issuer_cik', "id", 'file144', "file144" FROM file144s
+	UNION ALL SELECT schedule13->>'issuer_cik', "id", 'schedule13', "schedule13" FROM schedule13s;
+
+CREATE VIEW current_file4s AS SELECT * FROM file4s
+	WHERE "id" NOT IN (SELECT "amends" FROM amendments WHERE "amends" IS NOT NULL);
+
+CREATE TABLE issuers ("cik"	TEXT NOT NULL PRIMARY KEY,
+	"name"	TEXT NOT NULL,
+	"symbol"	TEXT);
+
+CREATE TABLE reporters ("cik"	TEXT NOT NULL PRIMARY KEY,
+	"name"	TEXT NOT NULL);
+
+CREATE TABLE filings ("id"	TEXT NOT NULL PRIMARY KEY,
+	"file_name"	TEXT NOT NULL,
+	"document_type"	TEXT,
+	"filed"	TEXT,
+	"original_date"	TEXT,
+	"issuer_cik"	TEXT NOT NULL REFERENCES issuers("cik"),
+	"planned_trade"	BOOLEAN);
+
+CREATE INDEX filings_issuer ON filings ("issuer_cik", "filed");
+
+CREATE INDEX filings_filed ON filings ("filed");
+
+CREATE TABLE filing_reporters ("filing_id"	TEXT NOT NULL REFERENCES filings("id"),
+	"reporter_cik"	TEXT NOT NULL REFERENCES reporters("cik"),
+	"position"	BIGINT NOT NULL,
+	"is_director"	BOOLEAN NOT NULL,
+	"is_officer"	BOOLEAN NOT NULL,
+	"is_ten_percent_owner"	BOOLEAN NOT NULL,
+	"is_other"	BOOLEAN NOT NULL,
+	"officer_title"	TEXT,
+	PRIMARY KEY("filing_id", "reporter_cik"));
+
+CREATE INDEX filing_reporters_reporter ON filing_reporters ("reporter_cik");
+
+CREATE TABLE non_derivative_transactions ("filing_id"	TEXT NOT NULL REFERENCES filings("id"),
+	"position"	BIGINT NOT NULL,
+	"title"	TEXT NOT NULL,
+	"date"	TEXT,
+	"codes"	TEXT,
+	"shares"	NUMERIC,
+	"acquired"	BOOLEAN,
+	"price"	NUMERIC,
+	"owned"	NUMERIC NOT NULL,
+	"ownership"	TEXT NOT NULL,
+	"nature"	TEXT,
+	"footnotes"	JSONB NOT NULL,
+	"transaction_footnotes"	JSONB,
+	PRIMARY KEY("filing_id", "position"));
+
+CREATE INDEX non_derivative_transactions_date ON non_derivative_transactions ("date");
+
+CREATE INDEX non_derivative_transactions_codes ON non_derivative_transactions ("codes");
+
+CREATE TABLE derivative_transactions ("filing_id"	TEXT NOT NULL REFERENCES filings("id"),
+	"position"	BIGINT NOT NULL,
+	"title"	TEXT NOT NULL,
+	"conversion_price"	NUMERIC,
+	"date"	TEXT,
+	"codes"	TEXT,
+	"shares"	NUMERIC,
+	"acquired"	BOOLEAN,
+	"price"	NUMERIC,
+	"exercisable"	TEXT,
+	"expiration"	TEXT,
+	"underlying_title"	TEXT,
+	"underlying_shares"	NUMERIC,
+	"owned"	NUMERIC NOT NULL,
+	"ownership"	TEXT NOT NULL,
+	"nature"	TEXT,
+	"footnotes"	JSONB NOT NULL,
+	"underlying_footnotes"	JSONB,
+	PRIMARY KEY("filing_id", "position"));
+
+CREATE INDEX derivative_transactions_date ON derivative_transactions ("date");
+
+CREATE INDEX derivative_transactions_codes ON derivative_transactions ("codes");
+
+CREATE TABLE footnotes ("filing_id"	TEXT NOT NULL REFERENCES filings("id"),
+	"id"	TEXT NOT NULL,
+	"text"	TEXT NOT NULL,
+	PRIMARY KEY("filing_id", "id"));
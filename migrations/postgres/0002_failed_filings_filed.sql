@@ -0,0 +1,2 @@
+-- Primary documents have no SGML header, so their filing date is kept for reprocess
+ALTER TABLE failed_filings ADD COLUMN "filed" TEXT;
@@ -0,0 +1,11 @@
+-- Sales are matched to notices by issuer, seller and filing date, without parsing every blob.
+-- The CIKs are kept without leading zeros, notices and ownership filings pad them differently.
+ALTER TABLE file144s ADD COLUMN "issuer_cik" TEXT,
+	ADD COLUMN "seller_cik" TEXT,
+	ADD COLUMN "filed" TEXT;
+
+UPDATE file144s SET "issuer_cik" = ltrim(file144->>'issuer_cik', '0'),
+	"seller_cik" = ltrim(file144->>'seller_cik', '0'),
+	"filed" = file144->>'filed';
+
+CREATE INDEX file144s_match ON file144s ("issuer_cik", "seller_cik", "filed");
@@ -0,0 +1,57 @@
+-- The tables connect() used to create on every start. Everything is IF NOT EXISTS so
+-- databases from before migrations are adopted as they are.
+
+CREATE TABLE IF NOT EXISTS file4s ("id"	TEXT NOT NULL UNIQUE,
+	"file4"	TEXT NOT NULL,
+	PRIMARY KEY("id"));
+
+CREATE TABLE IF NOT EXISTS cursors ("feed"	TEXT NOT NULL UNIQUE,
+	"last_change"	TEXT NOT NULL,
+	PRIMARY KEY("feed"));
+
+CREATE TABLE IF NOT EXISTS incomplete_polls ("feed"	TEXT NOT NULL,
+	"polled_at"	TEXT NOT NULL,
+	"last_change"	TEXT NOT NULL,
+	"oldest_seen"	TEXT);
+
+CREATE TABLE IF NOT EXISTS failed_filings ("url"	TEXT NOT NULL UNIQUE,
+	"accession"	TEXT,
+	"submission"	TEXT NOT NULL,
+	"error"	TEXT NOT NULL,
+	"failed_at"	TEXT NOT NULL,
+	PRIMARY KEY("url"));
+
+CREATE TABLE IF NOT EXISTS retries ("url"	TEXT NOT NULL UNIQUE,
+	"feed"	TEXT NOT NULL,
+	"attempts"	INTEGER NOT NULL,
+	"next_attempt"	TEXT NOT NULL,
+	"error"	TEXT NOT NULL,
+	PRIMARY KEY("url"));
+
+CREATE TABLE IF NOT EXISTS failed_downloads ("url"	TEXT NOT NULL UNIQUE,
+	"feed"	TEXT NOT NULL,
+	"attempts"	INTEGER NOT NULL,
+	"error"	TEXT NOT NULL,
+	"failed_at"	TEXT NOT NULL,
+	PRIMARY KEY("url"));
+
+CREATE TABLE IF NOT EXISTS amendments ("id"	TEXT NOT NULL UNIQUE,
+	"amends"	TEXT,
+	PRIMARY KEY("id"));
+
+CREATE TABLE IF NOT EXISTS file144s ("id"	TEXT NOT NULL UNIQUE,
+	"file144"	TEXT NOT NULL,
+	"file4"	TEXT,
+	PRIMARY KEY("id"));
+
+CREATE TABLE IF NOT EXISTS schedule13s ("id"	TEXT NOT NULL UNIQUE,
+	"schedule13"	TEXT NOT NULL,
+	PRIMARY KEY("id"));
+
+CREATE VIEW IF NOT EXISTS issuer_filings AS
+	SELECT json_extract(file4, '$.issuer.cik') AS "issuer_cik", "id", 'file4' AS "kind", "file4" AS "data" FROM file4s
+	UNION ALL SELECT json_extract(file144, '$.issuer_cik'), "id", 'file144', "file144" FROM file144s
+	UNION ALL SELECT json_extract(schedule13, '$.issuer_cik'), "id", 'schedule13', "schedule13" FROM schedule13s;
+
+CREATE VIEW IF NOT EXISTS current_file4s AS SELECT * FROM file4s
+	WHERE "id" NOT IN (SELECT "amends" FROM amendments WHERE "amends" IS NOT NULL);
@@ -0,0 +1,85 @@
+-- Ownership filings as relational tables next to the file4s JSON blobs, so questions
+-- like "all sales by reporter X" don't have to parse every row. Share counts and
+-- prices are TEXT to keep them exact, like they are in the blobs.
+
+CREATE TABLE IF NOT EXISTS issuers ("cik"	TEXT NOT NULL UNIQUE,
+	"name"	TEXT NOT NULL,
+	"symbol"	TEXT,
+	PRIMARY KEY("cik"));
+
+CREATE TABLE IF NOT EXISTS reporters ("cik"	TEXT NOT NULL UNIQUE,
+	"name"	TEXT NOT NULL,
+	PRIMARY KEY("cik"));
+
+CREATE TABLE IF NOT EXISTS filings ("id"	TEXT NOT NULL UNIQUE,
+	"file_name"	TEXT NOT NULL,
+	"document_type"	TEXT,
+	"filed"	TEXT,
+	"original_date"	TEXT,
+	"issuer_cik"	TEXT NOT NULL REFERENCES issuers("cik"),
+	"planned_trade"	INTEGER,
+	PRIMARY KEY("id"));
+
+CREATE INDEX IF NOT EXISTS filings_issuer ON filings ("issuer_cik", "filed");
+
+CREATE INDEX IF NOT EXISTS filings_filed ON filings ("filed");
+
+CREATE TABLE IF NOT EXISTS filing_reporters ("filing_id"	TEXT NOT NULL REFERENCES filings("id"),
+	"reporter_cik"	TEXT NOT NULL REFERENCES reporters("cik"),
+	"position"	INTEGER NOT NULL,
+	"is_director"	INTEGER NOT NULL,
+	"is_officer"	INTEGER NOT NULL,
+	"is_ten_percent_owner"	INTEGER NOT NULL,
+	"is_other"	INTEGER NOT NULL,
+	"officer_title"	TEXT,
+	PRIMARY KEY("filing_id", "reporter_cik"));
+
+CREATE INDEX IF NOT EXISTS filing_reporters_reporter ON filing_reporters ("reporter_cik");
+
+CREATE TABLE IF NOT EXISTS non_derivative_transactions ("filing_id"	TEXT NOT NULL REFERENCES filings("id"),
+	"position"	INTEGER NOT NULL,
+	"title"	TEXT NOT NULL,
+	"date"	TEXT,
+	"codes"	TEXT,
+	"shares"	TEXT,
+	"acquired"	INTEGER,
+	"price"	TEXT,
+	"owned"	TEXT NOT NULL,
+	"ownership"	TEXT NOT NULL,
+	"nature"	TEXT,
+	"footnotes"	TEXT NOT NULL,
+	"transaction_footnotes"	TEXT,
+	PRIMARY KEY("filing_id", "position"));
+
+CREATE INDEX IF NOT EXISTS non_derivative_transactions_date ON non_derivative_transactions ("date");
+
+CREATE INDEX IF NOT EXISTS non_derivative_transactions_codes ON non_derivative_transactions ("codes");
+
+CREATE TABLE IF NOT EXISTS derivative_transactions ("filing_id"	TEXT NOT NULL REFERENCES filings("id"),
+	"position"	INTEGER NOT NULL,
+	"title"	TEXT NOT NULL,
+	"conversion_price"	TEXT,
+	"date"	TEXT,
+	"codes"	TEXT,
+	"shares"	TEXT,
+	"acquired"	INTEGER,
+	"price"	TEXT,
+	"exercisable"	TEXT,
+	"expiration"	TEXT,
+	"underlying_title"	TEXT,
+	"underlying_shares"	TEXT,
+	"owned"	TEXT NOT NULL,
+	"ownership"	TEXT NOT NULL,
+	"nature"	TEXT,
+	"footnotes"	TEXT NOT NULL,
+	"underlying_footnotes"	TEXT,
+	PRIMARY KEY("filing_id", "position"));
+
+CREATE INDEX IF NOT EXISTS derivative_transactions_date ON derivative_transactions ("date");
+
+CREATE INDEX IF NOT EXISTS derivative_transactions_codes ON derivative_transactions ("codes");
+
+CREATE TABLE IF NOT EXISTS footnotes ("filing_id"	TEXT NOT NULL REFERENCES filings("id"),
+	"id"	TEXT NOT NULL,
+	"text"	TEXT NOT NULL,
+	PRIMARY KEY("filing_id", "id"));
@@ -0,0 +1,56 @@
+-- One time expansion of the file4s blobs stored before the relational tables existed.
+-- Later filings are written to both by db::insert_file4.
+-- Blobs from before derivativeTable parsing have no conversion_price key, their derivative
+-- array was filled from nonDerivativeTable and would only duplicate the stock trades.
+
+INSERT OR IGNORE INTO issuers ("cik", "name", "symbol")
+	SELECT json_extract(file4, '$.issuer.cik'), json_extract(file4, '$.issuer.name'), json_extract(file4, '$.issuer.symbol')
+	FROM file4s ORDER BY "id" DESC;
+
+INSERT OR IGNORE INTO reporters ("cik", "name")
+	SELECT json_extract(r.value, '$.cik'), json_extract(r.value, '$.name')
+	FROM file4s, json_each(file4, '$.reporters') AS r ORDER BY file4s."id" DESC;
+
+INSERT OR IGNORE INTO filings ("id", "file_name", "document_type", "filed", "original_date", "issuer_cik", "planned_trade")
+	SELECT "id", json_extract(file4, '$.file_name'), json_extract(file4, '$.document_type'), json_extract(file4, '$.filed'),
+	json_extract(file4, '$.original_date'), json_extract(file4, '$.issuer.cik'), json_extract(file4, '$.planned_trade')
+	FROM file4s;
+
+INSERT OR IGNORE INTO filing_reporters ("filing_id", "reporter_cik", "position", "is_director", "is_officer", "is_ten_percent_owner", "is_other", "officer_title")
+	SELECT file4s."id", json_extract(r.value, '$.cik'), r.key,
+	EXISTS (SELECT 1 FROM json_each(r.value, '$.relation.relations') WHERE value = 'Director'),
+	EXISTS (SELECT 1 FROM json_each(r.value, '$.relation.relations') WHERE value = 'Officer'),
+	EXISTS (SELECT 1 FROM json_each(r.value, '$.relation.relations') WHERE value = 'Owner'),
+	EXISTS (SELECT 1 FROM json_each(r.value, '$.relation.relations') WHERE value = 'Other'),
+	json_extract(r.value, '$.relation.title')
+	FROM file4s, json_each(file4, '$.reporters') AS r;
+
+INSERT OR IGNORE INTO non_derivative_transactions ("filing_id", "position", "title", "date", "codes", "shares", "acquired", "price", "owned", "ownership", "nature", "footnotes", "transaction_footnotes")
+	SELECT file4s."id", t.key, json_extract(t.value, '$.title'), json_extract(t.value, '$.date'),
+	(SELECT group_concat(value, '') FROM json_each(t.value, '$.tx_codes')),
+	json_extract(t.value, '$.tx_data.amount'), json_extract(t.value, '$.tx_data.acqired'), json_extract(t.value, '$.tx_data.price'),
+	json_extract(t.value, '$.owned'),
+	CASE WHEN json_extract(t.value, '$.ownership') = 'Direct' THEN 'D' ELSE 'I' END,
+	json_extract(t.value, '$.ownership.Indirect'),
+	coalesce(json_extract(t.value, '$.footnotes'), '[]'),
+	CASE WHEN json_type(t.value, '$.tx_data') = 'object' THEN coalesce(json_extract(t.value, '$.tx_data.footnotes'), '[]') END
+	FROM file4s, json_each(file4, '$.non_derivative') AS t;
+
+INSERT OR IGNORE INTO derivative_transactions ("filing_id", "position", "title", "conversion_price", "date", "codes", "shares", "acquired", "price", "exercisable", "expiration", "underlying_title", "underlying_shares", "owned", "ownership", "nature", "footnotes", "underlying_footnotes")
+	SELECT file4s."id", t.key, json_extract(t.value, '$.title'), json_extract(t.value, '$.conversion_price'), json_extract(t.value, '$.date'),
+	(SELECT group_concat(value, '') FROM json_each(t.value, '$.tx_codes')),
+	coalesce(json_extract(t.value, '$.count.Acquired'), json_extract(t.value, '$.count.Disposed')),
+	CASE WHEN json_extract(t.value, '$.count.Acquired') IS NOT NULL THEN 1 WHEN json_extract(t.value, '$.count.Disposed') IS NOT NULL THEN 0 END,
+	json_extract(t.value, '$.price'), json_extract(t.value, '$.exercisable'), json_extract(t.value, '$.expiration'),
+	json_extract(t.value, '$.underlying.title'), coalesce(json_extract(t.value, '$.underlying.shares'), json_extract(t.value, '$.underlying.price')),
+	json_extract(t.value, '$.owned'),
+	CASE WHEN json_extract(t.value, '$.ownership') = 'Direct' THEN 'D' ELSE 'I' END,
+	json_extract(t.value, '$.ownership.Indirect'),
+	coalesce(json_extract(t.value, '$.footnotes'), '[]'),
+	CASE WHEN json_type(t.value, '$.underlying') = 'object' THEN coalesce(json_extract(t.value, '$.underlying.footnotes'), '[]') END
+	FROM file4s, json_each(file4, '$.derivative') AS t
+	WHERE json_type(t.value, '$.conversion_price') IS NOT NULL;
+
+INSERT OR IGNORE INTO footnotes ("filing_id", "id", "text")
+	SELECT file4s."id", json_extract(f.value, '$.id'), json_extract(f.value, '$.text')
+	FROM file4s, json_each(file4, '$.footnotes') AS f;
@@ -0,0 +1,2 @@
+-- Storage::known looks up every feed entry by accession, Postgres has had this index from the start
+CREATE INDEX IF NOT EXISTS failed_filings_accession ON failed_filings ("accession");
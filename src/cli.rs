@@ -0,0 +1,165 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::db::TransactionFilter;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Watches SEC insider filings and sends alerts on large trades"
+)]
+pub struct Cli {
+    // Without a subcommand it watches, like it always did
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Poll the SEC feeds, store new filings and send alerts
+    Watch,
+    /// Fetch past form 4 filings from the quarterly full indexes
+    Backfill {
+        /// First filing date, YYYY-MM-DD
+        #[arg(long)]
+        from: NaiveDate,
+        /// Last filing date, YYYY-MM-DD, defaults to today
+        #[arg(long)]
+        to: Option<NaiveDate>,
+    },
+    /// Parse a local submission file and print it, nothing is stored
+    Parse {
+        /// A .txt submission or its primary .xml document
+        file: PathBuf,
+        #[arg(long, value_enum, default_value_t = Output::Table)]
+        format: Output,
+    },
+    /// Parse quarantined filings again after a parser fix and requeue downloads that gave up
+    Reprocess,
+    /// Load an Insider Transactions Data Set zip
+    Import { zip: String },
+    /// List stored transactions, newest first
+    Query {
+        #[command(flatten)]
+        filter: Filter,
+    },
+    /// Write stored transactions as CSV or JSON lines
+    Export {
+        #[command(flatten)]
+        filter: Filter,
+        #[arg(long, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+        /// Write to a file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Database maintenance
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+}
+
+impl Command {
+    // Only these talk to the SEC, everything else works on local files and the database
+    pub fn fetches(&self) -> bool {
+        matches!(self, Command::Watch | Command::Backfill { .. })
+    }
+
+    pub fn notifies(&self) -> bool {
+        matches!(self, Command::Watch)
+    }
+}
+
+#[derive(Subcommand)]
+pub enum DbCommand {
+    /// Apply pending migrations and show where the database is at
+    Migrate,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Output {
+    Table,
+    Json,
+}
+
+#[derive(Args)]
+pub struct Filter {
+    /// Issuer CIK
+    #[arg(long)]
+    issuer: Option<String>,
+    /// Reporting owner CIK
+    #[arg(long)]
+    reporter: Option<String>,
+    /// Transaction code, like P or S
+    #[arg(long)]
+    code: Option<String>,
+    /// Earliest transaction date, YYYY-MM-DD
+    #[arg(long)]
+    from: Option<NaiveDate>,
+    /// Latest transaction date, YYYY-MM-DD
+    #[arg(long)]
+    to: Option<NaiveDate>,
+    #[arg(long)]
+    limit: Option<i64>,
+}
+
+impl Filter {
+    pub fn into_filter(self, limit: i64) -> TransactionFilter {
+        TransactionFilter {
+            issuer: self.issuer,
+            reporter: self.reporter,
+            code: self.code,
+            from: self.from.map(|d| d.to_string()),
+            to: self.to.map(|d| d.to_string()),
+            limit: self.limit.unwrap_or(limit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn parses_subcommands() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from(["file4_watcher"]).unwrap();
+        assert!(cli.command.is_none());
+
+        let cli = Cli::try_parse_from([
+            "file4_watcher",
+            "export",
+            "--code",
+            "S",
+            "--from",
+            "2024-01-01",
+            "--format",
+            "json",
+        ])
+        .unwrap();
+        let Some(Command::Export { filter, format, .. }) = cli.command else {
+            panic!("not an export");
+        };
+        assert!(matches!(format, Format::Json));
+        let filter = filter.into_filter(i64::MAX);
+        assert_eq!(filter.code.as_deref(), Some("S"));
+        assert_eq!(filter.from.as_deref(), Some("2024-01-01"));
+        assert_eq!(filter.limit, i64::MAX);
+
+        assert!(
+            Cli::try_parse_from(["file4_watcher", "backfill", "--from", "2024-13-01"]).is_err()
+        );
+    }
+}
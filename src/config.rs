@@ -0,0 +1,341 @@
+use std::{env::var, fmt::Display, fs, path::Path, str::FromStr, time::Duration};
+
+use anyhow::{Context, anyhow, bail};
+use rust_decimal::{Decimal, dec};
+use serde::Deserialize;
+
+use crate::{Feed, cli::Command};
+
+const DEFAULT_PATH: &str = "file4_watcher.toml";
+// With the backoff capped at a day this already keeps trying for weeks
+const MAX_ATTEMPTS: i64 = 20;
+
+// Everything in here can be left out, missing values fall back to what the watcher
+// always did. Env vars win over the file.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub storage: Storage,
+    pub http: Http,
+    pub thresholds: Thresholds,
+    pub retries: Retries,
+    pub notify: Notify,
+    pub feeds: Vec<FeedConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Storage {
+    pub url: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Http {
+    pub user_agent: Option<String>,
+    // SEC allows 10 requests per second across everything we fetch
+    pub rate_limit: u32,
+    // Parallel downloads, they all still share the rate_limit
+    pub workers: usize,
+    // Fetch only the ownership xml through the filing index instead of the whole submission
+    pub primary_xml: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Thresholds {
+    pub percentage: Decimal,
+    pub notice_value: Decimal,
+    pub suppress_planned: bool,
+}
+
+// Failed downloads are retried after backoff, 2x, 4x... before they are given up on
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Retries {
+    pub attempts: i64,
+    pub backoff_secs: u64,
+    pub interval_secs: u64,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Notify {
+    pub webhooks: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FeedConfig {
+    pub url: String,
+    pub kind: Feed,
+    pub forms: Vec<String>,
+    #[serde(default = "default_pages")]
+    pub pages: usize,
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            storage: Storage::default(),
+            http: Http::default(),
+            thresholds: Thresholds::default(),
+            retries: Retries::default(),
+            notify: Notify::default(),
+            feeds: vec![
+                feed("4", Feed::Ownership, &["4", "4/A"]),
+                feed("3", Feed::Ownership, &["3", "3/A"]),
+                feed("5", Feed::Ownership, &["5", "5/A"]),
+                feed("144", Feed::Notice, &["144", "144/A"]),
+                // Only the structured filings, the old SC 13D/G ones are plain HTML with nothing to parse
+                feed(
+                    "SCHEDULE%2013",
+                    Feed::Schedule,
+                    &[
+                        "SCHEDULE 13D",
+                        "SCHEDULE 13D/A",
+                        "SCHEDULE 13G",
+                        "SCHEDULE 13G/A",
+                    ],
+                ),
+            ],
+        }
+    }
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self {
+            url: "sqlite://db.sqlite".to_owned(),
+        }
+    }
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Self {
+            user_agent: None,
+            rate_limit: 10,
+            workers: 8,
+            primary_xml: false,
+        }
+    }
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            percentage: dec!(20),
+            notice_value: dec!(1_000_000),
+            suppress_planned: false,
+        }
+    }
+}
+
+impl Default for Retries {
+    fn default() -> Self {
+        Self {
+            attempts: 6,
+            backoff_secs: 60,
+            interval_secs: 30,
+        }
+    }
+}
+
+fn feed(kind: &str, feed: Feed, forms: &[&str]) -> FeedConfig {
+    FeedConfig {
+        url: format!(
+            "https://www.sec.gov/cgi-bin/browse-edgar?action=getcurrent&CIK=&type={}&company=&dateb=&owner=include&output=atom",
+            kind
+        ),
+        kind: feed,
+        forms: forms.iter().map(|f| f.to_string()).collect(),
+        pages: default_pages(),
+        interval_secs: default_interval(),
+    }
+}
+
+fn default_pages() -> usize {
+    10
+}
+
+fn default_interval() -> u64 {
+    30
+}
+
+impl Config {
+    // CONFIG points at the file, without it file4_watcher.toml is used if there is one
+    pub fn load(command: &Command) -> anyhow::Result<Self> {
+        let path = var("CONFIG").ok();
+        let mut config = match &path {
+            Some(path) => Self::read(path)?,
+            None if Path::new(DEFAULT_PATH).exists() => Self::read(DEFAULT_PATH)?,
+            None => Self::default(),
+        };
+        config.apply_env()?;
+        config.validate(command)?;
+        Ok(config)
+    }
+
+    fn read(path: &str) -> anyhow::Result<Self> {
+        let data = fs::read_to_string(path).with_context(|| format!("Can't read {}", path))?;
+        toml::from_str(&data).with_context(|| format!("Invalid config in {}", path))
+    }
+
+    fn apply_env(&mut self) -> anyhow::Result<()> {
+        if let Ok(url) = var("DATABASE_URL") {
+            self.storage.url = url;
+        }
+        if let Ok(user_agent) = var("USER_AGENT") {
+            self.http.user_agent = Some(user_agent);
+        }
+        if let Ok(webhooks) = var("WEBHOOK") {
+            self.notify.webhooks = webhooks.split(',').map(|w| w.trim().to_owned()).collect();
+        }
+        env("RATE_LIMIT", &mut self.http.rate_limit)?;
+        env("WORKERS", &mut self.http.workers)?;
+        env("PRIMARY_XML", &mut self.http.primary_xml)?;
+        env("PERCENTAGE", &mut self.thresholds.percentage)?;
+        env("NOTICE_VALUE", &mut self.thresholds.notice_value)?;
+        env("SUPPRESS_PLANNED", &mut self.thresholds.suppress_planned)?;
+        env("RETRY_ATTEMPTS", &mut self.retries.attempts)?;
+        Ok(())
+    }
+
+    fn validate(&self, command: &Command) -> anyhow::Result<()> {
+        if self.storage.url.is_empty() {
+            bail!("storage.url is empty");
+        }
+        if command.fetches() && self.http.user_agent.is_none() {
+            bail!(
+                "No USER_AGENT env or http.user_agent set, SEC wants a contact like \"Name name@example.com\""
+            );
+        }
+        if command.notifies() && self.notify.webhooks.is_empty() {
+            bail!("No WEBHOOK env or notify.webhooks set");
+        }
+        if let Some(user_agent) = &self.http.user_agent
+            && !user_agent.contains('@')
+        {
+            bail!(
+                "http.user_agent \"{}\" has no email, SEC wants a contact like \"Name name@example.com\"",
+                user_agent
+            );
+        }
+        if self.http.rate_limit == 0 || self.http.rate_limit > 10 {
+            bail!("http.rate_limit must be between 1 and 10 requests per second");
+        }
+        if self.http.workers == 0 {
+            bail!("http.workers must be at least 1");
+        }
+        if self.thresholds.percentage.is_sign_negative() {
+            bail!("thresholds.percentage can't be negative");
+        }
+        if self.thresholds.notice_value.is_sign_negative() {
+            bail!("thresholds.notice_value can't be negative");
+        }
+        if !(1..=MAX_ATTEMPTS).contains(&self.retries.attempts) {
+            bail!("retries.attempts must be between 1 and {}", MAX_ATTEMPTS);
+        }
+        if self.retries.interval_secs == 0 {
+            bail!("retries.interval_secs must be at least 1");
+        }
+        for i in &self.notify.webhooks {
+            if !i.starts_with("https://") && !i.starts_with("http://") {
+                bail!("Webhook \"{}\" is not an http(s) url", i);
+            }
+        }
+        if self.feeds.is_empty() {
+            bail!("No feeds configured");
+        }
+        for i in &self.feeds {
+            if !i.url.starts_with("https://") && !i.url.starts_with("http://") {
+                bail!("Feed url \"{}\" is not an http(s) url", i.url);
+            }
+            if i.forms.is_empty() {
+                bail!("Feed {} has no forms", i.url);
+            }
+            if i.pages == 0 {
+                bail!("Feed {} needs at least 1 page", i.url);
+            }
+            if i.interval_secs == 0 {
+                bail!("Feed {} needs an interval_secs of at least 1", i.url);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn retry_backoff(&self) -> Duration {
+        Duration::from_secs(self.retries.backoff_secs)
+    }
+
+    pub fn retry_interval(&self) -> Duration {
+        Duration::from_secs(self.retries.interval_secs)
+    }
+}
+
+fn env<T: FromStr>(name: &str, value: &mut T) -> anyhow::Result<()>
+where
+    T::Err: Display,
+{
+    if let Ok(raw) = var(name) {
+        *value = raw
+            .parse()
+            .map_err(|e| anyhow!("Invalid {} env \"{}\": {}", name, raw, e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = include_str!("../file4_watcher.example.toml");
+
+    #[test]
+    fn parses_example_config() {
+        let config: Config = toml::from_str(EXAMPLE).unwrap();
+        config.validate(&Command::Watch).unwrap();
+        assert_eq!(config.thresholds.percentage, dec!(20));
+        assert_eq!(config.notify.webhooks.len(), 1);
+        assert_eq!(config.feeds.len(), 2);
+        assert!(matches!(config.feeds[1].kind, Feed::Notice));
+        assert_eq!(config.feeds[1].pages, 10);
+
+        // Sections that are left out keep the defaults
+        let config: Config = toml::from_str("[http]\nworkers = 2").unwrap();
+        assert_eq!(config.http.workers, 2);
+        assert_eq!(config.http.rate_limit, 10);
+        assert_eq!(config.feeds.len(), 5);
+    }
+
+    #[test]
+    fn rejects_bad_config() {
+        assert!(toml::from_str::<Config>("[http]\nrate = 5").is_err());
+        let config: Config = toml::from_str("[http]\nrate_limit = 50").unwrap();
+        assert!(config.validate(&Command::Reprocess).is_err());
+        let config: Config = toml::from_str("[retries]\nattempts = 40").unwrap();
+        assert!(config.validate(&Command::Reprocess).is_err());
+        let config: Config = toml::from_str(
+            "[[feeds]]\nurl = \"https://www.sec.gov/\"\nkind = \"ownership\"\nforms = []",
+        )
+        .unwrap();
+        assert!(config.validate(&Command::Reprocess).is_err());
+
+        // Only the commands that need them want a user agent and webhooks
+        let config = Config::default();
+        config.validate(&Command::Reprocess).unwrap();
+        assert!(config.validate(&Command::Watch).is_err());
+        let config: Config =
+            toml::from_str("[http]\nuser_agent = \"Name name@example.com\"").unwrap();
+        config
+            .validate(&Command::Backfill {
+                from: Default::default(),
+                to: None,
+            })
+            .unwrap();
+        assert!(config.validate(&Command::Watch).is_err());
+    }
+}
@@ -0,0 +1,417 @@
+use std::{collections::HashMap, fs::File, io::Read};
+
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use zip::ZipArchive;
+
+use crate::file4::{
+    Derivative, DerivativeNumber, File4, Footnote, Issuer, NonDerivative, Ownership, Relation,
+    Relations, Reporter, TransactionCode, TransactionData, Underlying, planned_footnote,
+};
+
+const TABLES: [&str; 7] = [
+    "SUBMISSION",
+    "REPORTINGOWNER",
+    "NONDERIV_TRANS",
+    "NONDERIV_HOLDING",
+    "DERIV_TRANS",
+    "DERIV_HOLDING",
+    "FOOTNOTES",
+];
+
+// One of the quarterly Insider Transactions Data Sets, e.g. 2024q1_form345.zip
+pub fn read(path: &str) -> anyhow::Result<Vec<File4>> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut tables = HashMap::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let Some(name) = file
+            .name()
+            .rsplit('/')
+            .next()
+            .and_then(|n| n.strip_suffix(".tsv"))
+            .map(|n| n.to_ascii_uppercase())
+        else {
+            continue;
+        };
+        if !TABLES.contains(&name.as_str()) {
+            continue;
+        }
+        let mut data = String::new();
+        file.read_to_string(&mut data)?;
+        tables.insert(name, data);
+    }
+    if !tables.contains_key("SUBMISSION") {
+        return Err(anyhow!("No SUBMISSION.tsv found in {}", path));
+    }
+    Ok(parse(&tables))
+}
+
+pub fn parse(tables: &HashMap<String, String>) -> Vec<File4> {
+    let table = |name: &str| Table::new(tables.get(name).map(|t| t.as_str()).unwrap_or_default());
+    let owners = table("REPORTINGOWNER");
+    let non_derivative_trans = table("NONDERIV_TRANS");
+    let non_derivative_holding = table("NONDERIV_HOLDING");
+    let derivative_trans = table("DERIV_TRANS");
+    let derivative_holding = table("DERIV_HOLDING");
+    let footnotes = table("FOOTNOTES");
+
+    let mut out = Vec::new();
+    for i in table("SUBMISSION").rows() {
+        let Some(id) = i.get("ACCESSION_NUMBER") else {
+            continue;
+        };
+        let Some(issuer) = (|| {
+            Some(Issuer {
+                cik: i.get("ISSUERCIK")?.to_owned(),
+                name: i.get("ISSUERNAME")?.to_owned(),
+                symbol: i.get("ISSUERTRADINGSYMBOL").unwrap_or_default().to_owned(),
+            })
+        })() else {
+            eprintln!("Skipping {}: no issuer", id);
+            continue;
+        };
+
+        let reporters = owners
+            .rows_for(id)
+            .filter_map(|r| {
+                let relations = r
+                    .get("RPTOWNER_RELATIONSHIP")
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter_map(|r| match r.trim() {
+                        "Director" => Some(Relation::Director),
+                        "Officer" => Some(Relation::Officer),
+                        "TenPercentOwner" => Some(Relation::Owner),
+                        "Other" => Some(Relation::Other),
+                        _ => None,
+                    })
+                    .collect();
+                Some(Reporter {
+                    cik: r.get("RPTOWNERCIK")?.to_owned(),
+                    name: r.get("RPTOWNERNAME")?.to_owned(),
+                    relation: Relations {
+                        relations,
+                        title: r.get("RPTOWNER_TITLE").map(|t| t.to_owned()),
+                    },
+                })
+            })
+            .collect();
+
+        let non_derivative: Option<Vec<_>> = non_derivative_trans
+            .rows_for(id)
+            .chain(non_derivative_holding.rows_for(id))
+            .map(|r| {
+                Some(NonDerivative {
+                    title: r.get("SECURITY_TITLE")?.to_owned(),
+                    date: r.get("TRANS_DATE").map(date),
+                    tx_codes: r.get("TRANS_CODE").and_then(TransactionCode::from_str),
+                    tx_data: (|| {
+                        Some(TransactionData {
+                            amount: r.decimal("TRANS_SHARES")?,
+                            acqired: r.get("TRANS_ACQUIRED_DISP_CD")? == "A",
+                            price: r.decimal("TRANS_PRICEPERSHARE"),
+                            footnotes: r.footnotes(&[
+                                "TRANS_SHARES_FN",
+                                "TRANS_PRICEPERSHARE_FN",
+                                "TRANS_ACQUIRED_DISP_CD_FN",
+                            ]),
+                        })
+                    })(),
+                    owned: r.decimal("SHRS_OWND_FOLWNG_TRANS")?,
+                    ownership: r.ownership()?,
+                    footnotes: r.footnotes(&[
+                        "SECURITY_TITLE_FN",
+                        "TRANS_DATE_FN",
+                        "TRANS_CODING_FN",
+                        "SHRS_OWND_FOLWNG_TRANS_FN",
+                        "DIRECT_INDIRECT_OWNERSHIP_FN",
+                        "NATURE_OF_OWNERSHIP_FN",
+                    ]),
+                })
+            })
+            .collect();
+
+        let derivative: Option<Vec<_>> = derivative_trans
+            .rows_for(id)
+            .chain(derivative_holding.rows_for(id))
+            .map(|r| {
+                let underlying = r.get("UNDLYNG_SEC_TITLE").map(|title| Underlying {
+                    title: title.to_owned(),
+                    shares: r.decimal("UNDLYNG_SEC_SHARES"),
+                    footnotes: r.footnotes(&["UNDLYNG_SEC_TITLE_FN", "UNDLYNG_SEC_SHARES_FN"]),
+                });
+                // Form 3 holdings only report the position through the underlying security
+                let owned = r
+                    .decimal("SHRS_OWND_FOLWNG_TRANS")
+                    .or(underlying.as_ref().and_then(|u| u.shares))?;
+                Some(Derivative {
+                    title: r.get("SECURITY_TITLE")?.to_owned(),
+                    conversion_price: r.decimal("CONV_EXERCISE_PRICE"),
+                    date: r.get("TRANS_DATE").map(date),
+                    tx_codes: r.get("TRANS_CODE").and_then(TransactionCode::from_str),
+                    count: r.decimal("TRANS_SHARES").map(|count| {
+                        match r.get("TRANS_ACQUIRED_DISP_CD") {
+                            Some("A") => DerivativeNumber::Acquired(count),
+                            _ => DerivativeNumber::Disposed(count),
+                        }
+                    }),
+                    price: r.decimal("TRANS_PRICEPERSHARE"),
+                    // The data sets spell this column with an extra c
+                    exercisable: r.get("EXCERCISE_DATE").or(r.get("EXERCISE_DATE")).map(date),
+                    expiration: r.get("EXPIRATION_DATE").map(date),
+                    underlying,
+                    owned,
+                    ownership: r.ownership()?,
+                    footnotes: r.footnotes(&[
+                        "SECURITY_TITLE_FN",
+                        "CONV_EXERCISE_PRICE_FN",
+                        "TRANS_DATE_FN",
+                        "TRANS_CODING_FN",
+                        "TRANS_SHARES_FN",
+                        "TRANS_PRICEPERSHARE_FN",
+                        "EXCERCISE_DATE_FN",
+                        "EXPIRATION_DATE_FN",
+                        "SHRS_OWND_FOLWNG_TRANS_FN",
+                        "DIRECT_INDIRECT_OWNERSHIP_FN",
+                        "NATURE_OF_OWNERSHIP_FN",
+                    ]),
+                })
+            })
+            .collect();
+
+        let (Some(non_derivative), Some(derivative)) = (non_derivative, derivative) else {
+            eprintln!("Skipping {}: incomplete holdings", id);
+            continue;
+        };
+
+        let footnotes: Vec<Footnote> = footnotes
+            .rows_for(id)
+            .filter_map(|r| {
+                Some(Footnote {
+                    id: r.get("FOOTNOTE_ID")?.to_owned(),
+                    text: r.get("FOOTNOTE_TXT").unwrap_or_default().to_owned(),
+                })
+            })
+            .collect();
+
+        // The column is empty for forms from before the checkbox, like in the xml
+        let planned_trade = i
+            .get("AFF10B5ONE")
+            .map(|t| matches!(t, "1" | "true" | "Y"))
+            .or_else(|| planned_footnote(&footnotes));
+
+        out.push(File4 {
+            id: id.to_owned(),
+            file_name: String::new(),
+            document_type: i.get("DOCUMENT_TYPE").map(|t| t.to_owned()),
+            filed: i.get("FILING_DATE").map(date),
+            original_date: i.get("DATE_OF_ORIG_SUB").map(date),
+            reporters,
+            issuer,
+            non_derivative,
+            derivative,
+            footnotes,
+            planned_trade,
+        });
+    }
+    // Originals have to be stored before their amendments can be linked to them
+    out.sort_by(|a, b| (&a.filed, &a.id).cmp(&(&b.filed, &b.id)));
+    out
+}
+
+// The data sets write dates as 05-MAR-2024, everything else we store uses YYYY-MM-DD
+fn date(text: &str) -> String {
+    NaiveDate::parse_from_str(text, "%d-%b-%Y")
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|_| text.to_owned())
+}
+
+struct Table<'a> {
+    columns: HashMap<&'a str, usize>,
+    rows: HashMap<&'a str, Vec<Vec<&'a str>>>,
+    order: Vec<&'a str>,
+}
+
+struct Row<'t, 'a> {
+    table: &'t Table<'a>,
+    fields: &'t [&'a str],
+}
+
+impl<'a> Table<'a> {
+    fn new(data: &'a str) -> Self {
+        let mut lines = data.lines();
+        let columns: HashMap<&str, usize> = lines
+            .next()
+            .unwrap_or_default()
+            .split('\t')
+            .enumerate()
+            .map(|(n, c)| (c.trim(), n))
+            .collect();
+        let mut rows: HashMap<&str, Vec<Vec<&str>>> = HashMap::new();
+        let mut order = Vec::new();
+        let Some(&accession) = columns.get("ACCESSION_NUMBER") else {
+            return Self {
+                columns,
+                rows,
+                order,
+            };
+        };
+        for line in lines {
+            let fields: Vec<&str> = line.trim_end_matches('\r').split('\t').collect();
+            let Some(id) = fields.get(accession).copied() else {
+                continue;
+            };
+            let entry = rows.entry(id).or_default();
+            if entry.is_empty() {
+                order.push(id);
+            }
+            entry.push(fields);
+        }
+        Self {
+            columns,
+            rows,
+            order,
+        }
+    }
+
+    fn rows(&self) -> impl Iterator<Item = Row<'_, 'a>> {
+        self.order.iter().flat_map(|id| self.rows_for(id))
+    }
+
+    fn rows_for(&self, id: &str) -> impl Iterator<Item = Row<'_, 'a>> {
+        self.rows.get(id).into_iter().flatten().map(|fields| Row {
+            table: self,
+            fields,
+        })
+    }
+}
+
+impl<'a> Row<'_, 'a> {
+    fn get(&self, column: &str) -> Option<&'a str> {
+        let n = *self.table.columns.get(column)?;
+        self.fields
+            .get(n)
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+    }
+
+    fn decimal(&self, column: &str) -> Option<Decimal> {
+        self.get(column)?.parse().ok()
+    }
+
+    fn ownership(&self) -> Option<Ownership> {
+        Some(match self.get("DIRECT_INDIRECT_OWNERSHIP")? {
+            "D" => Ownership::Direct,
+            _ => Ownership::Indirect(self.get("NATURE_OF_OWNERSHIP")?.to_owned()),
+        })
+    }
+
+    // Footnote columns hold a comma separated list of ids like F1,F2
+    fn footnotes(&self, columns: &[&str]) -> Vec<String> {
+        let mut out: Vec<String> = Vec::new();
+        for id in columns
+            .iter()
+            .filter_map(|c| self.get(c))
+            .flat_map(|t| t.split(','))
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+        {
+            if !out.iter().any(|i| i == id) {
+                out.push(id.to_owned());
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::dec;
+
+    use super::*;
+
+    #[test]
+    fn maps_data_set_tables() {
+        let tables = HashMap::from([
+            (
+                "SUBMISSION".to_owned(),
+                "ACCESSION_NUMBER\tFILING_DATE\tPERIOD_OF_REPORT\tDATE_OF_ORIG_SUB\tDOCUMENT_TYPE\tISSUERCIK\tISSUERNAME\tISSUERTRADINGSYMBOL\tAFF10B5ONE
+0001209191-24-031901\t06-MAR-2024\t01-MAR-2024\t05-MAR-2024\t4/A\t1654321\tExample Robotics, Inc.\tEXRB\t
+0001209191-24-031842\t05-MAR-2024\t01-MAR-2024\t\t4\t1654321\tExample Robotics, Inc.\tEXRB\t1
+"
+                .to_owned(),
+            ),
+            (
+                "REPORTINGOWNER".to_owned(),
+                "ACCESSION_NUMBER\tRPTOWNERCIK\tRPTOWNERNAME\tRPTOWNER_RELATIONSHIP\tRPTOWNER_TITLE
+0001209191-24-031842\t1771234\tDoe Jane\tDirector,Officer\tChief Executive Officer
+0001209191-24-031901\t1771234\tDoe Jane\tOfficer\tChief Executive Officer
+"
+                .to_owned(),
+            ),
+            (
+                "NONDERIV_TRANS".to_owned(),
+                "ACCESSION_NUMBER\tNONDERIV_TRANS_SK\tSECURITY_TITLE\tTRANS_DATE\tTRANS_CODE\tTRANS_SHARES\tTRANS_SHARES_FN\tTRANS_PRICEPERSHARE\tTRANS_ACQUIRED_DISP_CD\tSHRS_OWND_FOLWNG_TRANS\tDIRECT_INDIRECT_OWNERSHIP\tNATURE_OF_OWNERSHIP
+0001209191-24-031842\t1\tClass A Common Stock\t01-MAR-2024\tS\t4000\tF1\t34.515\tD\t58500\tD\t
+"
+                .to_owned(),
+            ),
+            (
+                "DERIV_HOLDING".to_owned(),
+                "ACCESSION_NUMBER\tDERIV_HOLDING_SK\tSECURITY_TITLE\tCONV_EXERCISE_PRICE\tEXCERCISE_DATE\tEXPIRATION_DATE\tUNDLYNG_SEC_TITLE\tUNDLYNG_SEC_SHARES\tDIRECT_INDIRECT_OWNERSHIP\tNATURE_OF_OWNERSHIP
+0001209191-24-031842\t2\tStock Option (Right to Buy)\t21.08\t\t17-MAR-2034\tClass A Common Stock\t40000\tI\tBy Trust
+"
+                .to_owned(),
+            ),
+            (
+                "FOOTNOTES".to_owned(),
+                "ACCESSION_NUMBER\tFOOTNOTE_ID\tFOOTNOTE_TXT
+0001209191-24-031842\tF1\tSold pursuant to a Rule 10b5-1 trading plan.
+0001209191-24-031901\tF1\tThe reported sale was not made pursuant to a Rule 10b5-1 trading plan.
+"
+                .to_owned(),
+            ),
+        ]);
+
+        let filings = parse(&tables);
+        assert_eq!(filings.len(), 2);
+
+        let file4 = &filings[0];
+        assert_eq!(file4.id, "0001209191-24-031842");
+        assert_eq!(file4.filed.as_deref(), Some("2024-03-05"));
+        assert_eq!(file4.issuer.symbol, "EXRB");
+        assert_eq!(file4.planned_trade, Some(true));
+        assert!(matches!(
+            file4.reporters[0].relation.relations[..],
+            [Relation::Director, Relation::Officer]
+        ));
+
+        let sale = &file4.non_derivative[0];
+        assert_eq!(sale.date.as_deref(), Some("2024-03-01"));
+        assert!(matches!(
+            sale.tx_codes.as_deref(),
+            Some([TransactionCode::S])
+        ));
+        let data = sale.tx_data.as_ref().unwrap();
+        assert_eq!(data.amount, dec!(4000));
+        assert_eq!(data.price, Some(dec!(34.515)));
+        assert!(!data.acqired);
+        assert_eq!(data.footnotes, ["F1"]);
+        assert_eq!(sale.owned, dec!(58500));
+
+        let option = &file4.derivative[0];
+        assert!(option.count.is_none());
+        assert_eq!(option.expiration.as_deref(), Some("2034-03-17"));
+        assert_eq!(option.owned, dec!(40000));
+        assert!(matches!(&option.ownership, Ownership::Indirect(n) if n == "By Trust"));
+
+        let amendment = &filings[1];
+        assert!(amendment.is_amendment());
+        assert_eq!(amendment.original_date.as_deref(), Some("2024-03-05"));
+        assert!(amendment.non_derivative.is_empty());
+        // No checkbox, the footnote decides
+        assert_eq!(amendment.planned_trade, Some(false));
+    }
+}
@@ -0,0 +1,248 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    file4::{File4, File4Error, Ownership, TransactionCode},
+    form144::File144,
+    schedule13::Schedule13,
+};
+
+mod postgres;
+mod sqlite;
+
+const CURSOR_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+// Retries never wait longer than a day, however many attempts are allowed
+const MAX_BACKOFF: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub type Db = Arc<dyn Storage>;
+
+// Everything the watcher keeps, so a shared Postgres can stand in for db.sqlite
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn insert_file4(&self, file4: &File4) -> bool;
+    async fn insert_file144(&self, file144: &File144) -> bool;
+    async fn insert_schedule13(&self, schedule13: &Schedule13) -> bool;
+    async fn known(&self, id: &str) -> bool;
+    async fn transactions(&self, filter: &TransactionFilter) -> Vec<Transaction>;
+    async fn cursor(&self, feed: &str) -> Option<NaiveDateTime>;
+    async fn set_cursor(&self, feed: &str, last_change: NaiveDateTime);
+    async fn record_incomplete(
+        &self,
+        feed: &str,
+        last_change: NaiveDateTime,
+        oldest_seen: Option<NaiveDateTime>,
+    );
+    // filed is kept for primary documents, a full submission carries its own date
+    async fn quarantine(
+        &self,
+        url: &str,
+        error: &File4Error,
+        submission: String,
+        filed: Option<&str>,
+    );
+    async fn failed_filings(&self) -> Vec<(String, String, Option<String>)>;
+    async fn release(&self, url: &str);
+    async fn retry_later(
+        &self,
+        url: &str,
+        feed: &str,
+        error: &str,
+        max_attempts: i64,
+        backoff: Duration,
+    );
+    async fn due_retries(&self) -> Vec<(String, String)>;
+    async fn release_retry(&self, url: &str);
+    // Gives downloads that ran out of attempts a fresh set, returns their urls and last errors
+    async fn requeue_failed_downloads(&self) -> Vec<(String, String)>;
+    async fn migrations(&self) -> Vec<(i64, String)>;
+}
+
+#[derive(Serialize, Debug)]
+pub struct Transaction {
+    pub filing_id: String,
+    pub filed: Option<String>,
+    pub issuer_cik: String,
+    pub issuer_name: String,
+    pub symbol: Option<String>,
+    pub reporter_cik: Option<String>,
+    pub reporter_name: Option<String>,
+    pub title: String,
+    pub date: Option<String>,
+    pub codes: Option<String>,
+    pub shares: Option<Decimal>,
+    pub acquired: Option<bool>,
+    pub price: Option<Decimal>,
+    pub owned: Decimal,
+}
+
+// Non-derivative transactions of current (not amended) filings, newest first
+pub struct TransactionFilter {
+    pub issuer: Option<String>,
+    pub reporter: Option<String>,
+    pub code: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: i64,
+}
+
+impl Default for TransactionFilter {
+    fn default() -> Self {
+        Self {
+            issuer: None,
+            reporter: None,
+            code: None,
+            from: None,
+            to: None,
+            limit: 100,
+        }
+    }
+}
+
+pub async fn connect(url: &str) -> Db {
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        Arc::new(postgres::Postgres::connect(url).await.unwrap())
+    } else {
+        Arc::new(sqlite::Sqlite::connect(url).await.unwrap())
+    }
+}
+
+fn codes(codes: &Option<Vec<TransactionCode>>) -> Option<String> {
+    codes
+        .as_ref()
+        .map(|c| c.iter().map(|c| format!("{:?}", c)).collect())
+}
+
+// backoff, 2x, 4x... after each failed attempt
+fn backoff(base: Duration, attempts: i64) -> Duration {
+    let factor = 2u32.saturating_pow((attempts - 1).clamp(0, 31) as u32);
+    base.saturating_mul(factor).min(MAX_BACKOFF)
+}
+
+fn ownership(ownership: &Ownership) -> (&'static str, Option<String>) {
+    match ownership {
+        Ownership::Direct => ("D", None),
+        Ownership::Indirect(nature) => ("I", Some(nature.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use rust_decimal::dec;
+
+    use super::*;
+
+    #[test]
+    fn caps_backoff() {
+        let base = Duration::from_secs(60);
+        assert_eq!(backoff(base, 1), base);
+        assert_eq!(backoff(base, 3), base * 4);
+        assert_eq!(backoff(base, 40), MAX_BACKOFF);
+        assert_eq!(backoff(Duration::MAX, 2), MAX_BACKOFF);
+    }
+
+    const DERIVATIVE: &str = include_str!("../tests/fixtures/form4_derivative.txt");
+    const AMENDMENT: &str = include_str!("../tests/fixtures/form4_amendment.txt");
+
+    // Run against every backend, each test hands in a fresh empty database
+    pub async fn exercise(db: &dyn Storage) {
+        let file4 = File4::new(DERIVATIVE.to_owned()).unwrap();
+        assert!(!db.known(&file4.id).await);
+        assert!(db.insert_file4(&file4).await);
+        assert!(!db.insert_file4(&file4).await);
+        assert!(db.known(&file4.id).await);
+
+        let exercises = db
+            .transactions(&TransactionFilter {
+                reporter: Some("1771234".to_owned()),
+                code: Some("M".to_owned()),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(exercises.len(), 1);
+        assert_eq!(exercises[0].filing_id, file4.id);
+        assert_eq!(exercises[0].acquired, Some(true));
+        assert_eq!(exercises[0].shares, Some(dec!(12500)));
+        assert_eq!(exercises[0].owned, dec!(187340));
+        assert!(
+            db.transactions(&TransactionFilter {
+                issuer: Some("1".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .is_empty()
+        );
+
+        assert!(!db.migrations().await.is_empty());
+
+        let feed = "4 ,4/A ";
+        let last_change = NaiveDate::from_ymd_opt(2024, 3, 5)
+            .unwrap()
+            .and_hms_opt(18, 30, 15)
+            .unwrap();
+        assert_eq!(db.cursor(feed).await, None);
+        db.set_cursor(feed, last_change).await;
+        assert_eq!(db.cursor(feed).await, Some(last_change));
+
+        let url = "https://www.sec.gov/Archives/edgar/data/1771234/0001209191-24-000001.txt";
+        db.retry_later(url, "ownership", "HTTP 500", 2, Duration::ZERO)
+            .await;
+        assert_eq!(
+            db.due_retries().await,
+            [(url.to_owned(), "ownership".to_owned())]
+        );
+        db.retry_later(url, "ownership", "HTTP 500", 2, Duration::ZERO)
+            .await;
+        assert!(db.due_retries().await.is_empty());
+        assert_eq!(
+            db.requeue_failed_downloads().await,
+            [(url.to_owned(), "HTTP 500".to_owned())]
+        );
+        assert_eq!(db.due_retries().await.len(), 1);
+        assert!(db.requeue_failed_downloads().await.is_empty());
+        db.release_retry(url).await;
+
+        let error = File4::new("not a submission".to_owned()).unwrap_err();
+        db.quarantine(
+            url,
+            &error,
+            "not a submission".to_owned(),
+            Some("2024-03-05"),
+        )
+        .await;
+        assert_eq!(
+            db.failed_filings().await,
+            [(
+                url.to_owned(),
+                "not a submission".to_owned(),
+                Some("2024-03-05".to_owned())
+            )]
+        );
+        db.release(url).await;
+        assert!(db.failed_filings().await.is_empty());
+
+        // The amendment replaces the original filing in the results
+        let amendment = File4::new(AMENDMENT.to_owned()).unwrap();
+        assert!(db.insert_file4(&amendment).await);
+        let rows = db
+            .transactions(&TransactionFilter {
+                reporter: Some("1771234".to_owned()),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].filing_id, amendment.id);
+        assert_eq!(rows[0].price, Some(dec!(187.4523)));
+    }
+
+    #[tokio::test]
+    async fn sqlite_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("db.sqlite").display());
+        exercise(connect(&url).await.as_ref()).await;
+    }
+}
@@ -0,0 +1,500 @@
+use std::{str::FromStr, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{
+    PgPool, Row,
+    postgres::{PgConnectOptions, PgPoolOptions},
+    query, query_as, query_scalar,
+    types::Json,
+};
+
+use super::{CURSOR_FORMAT, Storage, Transaction, TransactionFilter, codes, ownership};
+use crate::{
+    file4::{DerivativeNumber, File4, File4Error, Relation, TransactionCode},
+    form144::File144,
+    schedule13::Schedule13,
+};
+
+pub struct Postgres {
+    pool: PgPool,
+}
+
+impl Postgres {
+    pub async fn connect(url: &str) -> sqlx::Result<Self> {
+        Self::connect_with(PgConnectOptions::from_str(url)?).await
+    }
+
+    pub async fn connect_with(options: PgConnectOptions) -> sqlx::Result<Self> {
+        let pool = PgPoolOptions::new().connect_with(options).await?;
+        sqlx::migrate!("./migrations/postgres").run(&pool).await?;
+        Ok(Self { pool })
+    }
+
+    async fn requeue(&self) -> sqlx::Result<Vec<(String, String)>> {
+        let mut tx = self.pool.begin().await?;
+        let failed = query_as("SELECT url, error FROM failed_downloads ORDER BY failed_at")
+            .fetch_all(&mut *tx)
+            .await?;
+        query(
+            r#"INSERT INTO retries (url, feed, attempts, next_attempt, error)
+	SELECT url, feed, 0, $1, error FROM failed_downloads
+	ON CONFLICT (url) DO UPDATE SET attempts = 0, next_attempt = excluded.next_attempt"#,
+        )
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+        query("DELETE FROM failed_downloads")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(failed)
+    }
+
+    // The blob and the relational rows go in together, a filing is never half stored
+    async fn insert_filing(&self, file4: &File4) -> sqlx::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        query("INSERT INTO file4s (id, file4) VALUES ($1, $2)")
+            .bind(file4.id.clone())
+            .bind(Json(file4))
+            .execute(&mut *tx)
+            .await?;
+        query(
+            r#"INSERT INTO issuers (cik, name, symbol) VALUES ($1, $2, $3)
+	ON CONFLICT (cik) DO UPDATE SET name = excluded.name, symbol = excluded.symbol"#,
+        )
+        .bind(&file4.issuer.cik)
+        .bind(&file4.issuer.name)
+        .bind(&file4.issuer.symbol)
+        .execute(&mut *tx)
+        .await?;
+        query(
+            "INSERT INTO filings (id, file_name, document_type, filed, original_date, issuer_cik, planned_trade) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(&file4.id)
+        .bind(&file4.file_name)
+        .bind(&file4.document_type)
+        .bind(&file4.filed)
+        .bind(&file4.original_date)
+        .bind(&file4.issuer.cik)
+        .bind(file4.planned_trade)
+        .execute(&mut *tx)
+        .await?;
+        for (n, i) in file4.reporters.iter().enumerate() {
+            query(
+                "INSERT INTO reporters (cik, name) VALUES ($1, $2) ON CONFLICT (cik) DO UPDATE SET name = excluded.name",
+            )
+            .bind(&i.cik)
+            .bind(&i.name)
+            .execute(&mut *tx)
+            .await?;
+            let has = |r: fn(&Relation) -> bool| i.relation.relations.iter().any(r);
+            query(
+                r#"INSERT INTO filing_reporters (filing_id, reporter_cik, position, is_director, is_officer, is_ten_percent_owner, is_other, officer_title)
+	VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT DO NOTHING"#,
+            )
+            .bind(&file4.id)
+            .bind(&i.cik)
+            .bind(n as i64)
+            .bind(has(|r| matches!(r, Relation::Director)))
+            .bind(has(|r| matches!(r, Relation::Officer)))
+            .bind(has(|r| matches!(r, Relation::Owner)))
+            .bind(has(|r| matches!(r, Relation::Other)))
+            .bind(&i.relation.title)
+            .execute(&mut *tx)
+            .await?;
+        }
+        for (n, i) in file4.non_derivative.iter().enumerate() {
+            let (ownership, nature) = ownership(&i.ownership);
+            query(
+                r#"INSERT INTO non_derivative_transactions (filing_id, position, title, date, codes, shares, acquired, price, owned, ownership, nature, footnotes, transaction_footnotes)
+	VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)"#,
+            )
+            .bind(&file4.id)
+            .bind(n as i64)
+            .bind(&i.title)
+            .bind(&i.date)
+            .bind(codes(&i.tx_codes))
+            .bind(i.tx_data.as_ref().map(|d| d.amount))
+            .bind(i.tx_data.as_ref().map(|d| d.acqired))
+            .bind(i.tx_data.as_ref().and_then(|d| d.price))
+            .bind(i.owned)
+            .bind(ownership)
+            .bind(nature)
+            .bind(Json(&i.footnotes))
+            .bind(i.tx_data.as_ref().map(|d| Json(&d.footnotes)))
+            .execute(&mut *tx)
+            .await?;
+        }
+        for (n, i) in file4.derivative.iter().enumerate() {
+            let (ownership, nature) = ownership(&i.ownership);
+            let (shares, acquired) = match i.count {
+                Some(DerivativeNumber::Acquired(n)) => (Some(n), Some(true)),
+                Some(DerivativeNumber::Disposed(n)) => (Some(n), Some(false)),
+                None => (None, None),
+            };
+            query(
+                r#"INSERT INTO derivative_transactions (filing_id, position, title, conversion_price, date, codes, shares, acquired, price, exercisable, expiration, underlying_title, underlying_shares, owned, ownership, nature, footnotes, underlying_footnotes)
+	VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18)"#,
+            )
+            .bind(&file4.id)
+            .bind(n as i64)
+            .bind(&i.title)
+            .bind(i.conversion_price)
+            .bind(&i.date)
+            .bind(codes(&i.tx_codes))
+            .bind(shares)
+            .bind(acquired)
+            .bind(i.price)
+            .bind(&i.exercisable)
+            .bind(&i.expiration)
+            .bind(i.underlying.as_ref().map(|u| u.title.clone()))
+            .bind(i.underlying.as_ref().and_then(|u| u.shares))
+            .bind(i.owned)
+            .bind(ownership)
+            .bind(nature)
+            .bind(Json(&i.footnotes))
+            .bind(i.underlying.as_ref().map(|u| Json(&u.footnotes)))
+            .execute(&mut *tx)
+            .await?;
+        }
+        for i in &file4.footnotes {
+            query(
+                "INSERT INTO footnotes (filing_id, id, text) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+            )
+            .bind(&file4.id)
+            .bind(&i.id)
+            .bind(&i.text)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+}
+
+#[async_trait]
+impl Storage for Postgres {
+    async fn insert_file4(&self, file4: &File4) -> bool {
+        if let Err(e) = self.insert_filing(file4).await {
+            if !e
+                .as_database_error()
+                .is_some_and(|e| e.is_unique_violation())
+            {
+                eprintln!("Failed to store {}: {}", file4.id, e);
+            }
+            return false;
+        }
+        if file4.is_amendment() {
+            // The original was filed that day, it wins over amendments from the same day
+            let amends: Option<String> = match &file4.original_date {
+                Some(date) => query_scalar(
+                    r#"SELECT f.id FROM filings f
+	JOIN filing_reporters fr ON fr.filing_id = f.id AND fr.position = 0
+	WHERE f.id != $1 AND f.issuer_cik = $2 AND fr.reporter_cik = $3 AND f.filed = $4
+	ORDER BY coalesce(f.document_type, '') LIKE '%/A', f.id DESC LIMIT 1"#,
+                )
+                .bind(file4.id.clone())
+                .bind(file4.issuer.cik.clone())
+                .bind(file4.reporters.first().map(|r| r.cik.clone()))
+                .bind(date)
+                .fetch_optional(&self.pool)
+                .await
+                .unwrap_or_default(),
+                None => None,
+            };
+            let _ = query("INSERT INTO amendments (id, amends) VALUES ($1, $2)")
+                .bind(file4.id.clone())
+                .bind(amends)
+                .execute(&self.pool)
+                .await;
+        }
+        if file4.non_derivative.iter().any(|i| {
+            i.tx_codes
+                .as_ref()
+                .is_some_and(|c| c.iter().any(|c| matches!(c, TransactionCode::S)))
+        }) {
+            // The sale belongs to the latest notice filed before it
+            let _ = query(
+                r#"UPDATE file144s SET file4 = $1 WHERE id = (SELECT id FROM file144s WHERE file4 IS NULL
+	AND issuer_cik = ltrim($2, '0') AND seller_cik = ltrim($3, '0')
+	AND ($4::text IS NULL OR filed <= $4)
+	ORDER BY filed DESC NULLS LAST,
+		file144->'sales'->0->>'date' DESC NULLS LAST, id DESC LIMIT 1)"#,
+            )
+            .bind(file4.id.clone())
+            .bind(file4.issuer.cik.clone())
+            .bind(file4.reporters.first().map(|r| r.cik.clone()))
+            .bind(file4.filed.clone())
+            .execute(&self.pool)
+            .await;
+        }
+        true
+    }
+
+    async fn insert_file144(&self, file144: &File144) -> bool {
+        query(
+            r#"INSERT INTO file144s (id, file144, issuer_cik, seller_cik, filed)
+	VALUES ($1, $2, ltrim($3, '0'), ltrim($4, '0'), $5)"#,
+        )
+        .bind(file144.id.clone())
+        .bind(Json(file144))
+        .bind(&file144.issuer_cik)
+        .bind(&file144.seller_cik)
+        .bind(&file144.filed)
+        .execute(&self.pool)
+        .await
+        .is_ok()
+    }
+
+    async fn insert_schedule13(&self, schedule13: &Schedule13) -> bool {
+        query("INSERT INTO schedule13s (id, schedule13) VALUES ($1, $2)")
+            .bind(schedule13.id.clone())
+            .bind(Json(schedule13))
+            .execute(&self.pool)
+            .await
+            .is_ok()
+    }
+
+    async fn known(&self, id: &str) -> bool {
+        query_scalar(
+            r#"SELECT EXISTS (SELECT 1 FROM file4s WHERE id = $1
+	UNION ALL SELECT 1 FROM file144s WHERE id = $1
+	UNION ALL SELECT 1 FROM schedule13s WHERE id = $1
+	UNION ALL SELECT 1 FROM failed_filings WHERE accession = $1)"#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or_default()
+    }
+
+    async fn transactions(&self, filter: &TransactionFilter) -> Vec<Transaction> {
+        let rows = query(
+            r#"SELECT t.filing_id, f.filed, f.issuer_cik, i.name, i.symbol, r.cik, r.name, t.title, t.date, t.codes, t.shares, t.acquired, t.price, t.owned
+	FROM non_derivative_transactions t
+	JOIN filings f ON f.id = t.filing_id
+	JOIN issuers i ON i.cik = f.issuer_cik
+	LEFT JOIN filing_reporters fr ON fr.filing_id = f.id AND fr.position = 0
+	LEFT JOIN reporters r ON r.cik = fr.reporter_cik
+	WHERE f.id NOT IN (SELECT amends FROM amendments WHERE amends IS NOT NULL)
+	AND ($1::text IS NULL OR ltrim(f.issuer_cik, '0') = ltrim($1, '0'))
+	AND ($2::text IS NULL OR EXISTS (SELECT 1 FROM filing_reporters x WHERE x.filing_id = f.id AND ltrim(x.reporter_cik, '0') = ltrim($2, '0')))
+	AND ($3::text IS NULL OR strpos(t.codes, $3) > 0)
+	AND ($4::text IS NULL OR t.date >= $4)
+	AND ($5::text IS NULL OR t.date <= $5)
+	ORDER BY t.date DESC, t.filing_id DESC, t.position
+	LIMIT $6"#,
+        )
+        .bind(&filter.issuer)
+        .bind(&filter.reporter)
+        .bind(&filter.code)
+        .bind(&filter.from)
+        .bind(&filter.to)
+        .bind(filter.limit)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_default();
+        rows.iter()
+            .map(|r| Transaction {
+                filing_id: r.get(0),
+                filed: r.get(1),
+                issuer_cik: r.get(2),
+                issuer_name: r.get(3),
+                symbol: r.get(4),
+                reporter_cik: r.get(5),
+                reporter_name: r.get(6),
+                title: r.get(7),
+                date: r.get(8),
+                codes: r.get(9),
+                shares: r.get(10),
+                acquired: r.get(11),
+                price: r.get(12),
+                owned: r.get(13),
+            })
+            .collect()
+    }
+
+    async fn cursor(&self, feed: &str) -> Option<NaiveDateTime> {
+        let cursor: String = query_scalar("SELECT last_change FROM cursors WHERE feed = $1")
+            .bind(feed)
+            .fetch_optional(&self.pool)
+            .await
+            .ok()??;
+        NaiveDateTime::parse_from_str(&cursor, CURSOR_FORMAT).ok()
+    }
+
+    async fn set_cursor(&self, feed: &str, last_change: NaiveDateTime) {
+        let _ = query(
+            "INSERT INTO cursors (feed, last_change) VALUES ($1, $2) ON CONFLICT (feed) DO UPDATE SET last_change = excluded.last_change",
+        )
+        .bind(feed)
+        .bind(last_change.format(CURSOR_FORMAT).to_string())
+        .execute(&self.pool)
+        .await;
+    }
+
+    async fn record_incomplete(
+        &self,
+        feed: &str,
+        last_change: NaiveDateTime,
+        oldest_seen: Option<NaiveDateTime>,
+    ) {
+        let _ = query(
+            "INSERT INTO incomplete_polls (feed, polled_at, last_change, oldest_seen) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(feed)
+        .bind(Utc::now().to_rfc3339())
+        .bind(last_change.format(CURSOR_FORMAT).to_string())
+        .bind(oldest_seen.map(|t| t.format(CURSOR_FORMAT).to_string()))
+        .execute(&self.pool)
+        .await;
+    }
+
+    async fn quarantine(
+        &self,
+        url: &str,
+        error: &File4Error,
+        submission: String,
+        filed: Option<&str>,
+    ) {
+        let _ = query(
+            r#"INSERT INTO failed_filings (url, accession, submission, error, failed_at, filed) VALUES ($1, $2, $3, $4, $5, $6)
+	ON CONFLICT (url) DO UPDATE SET accession = excluded.accession, submission = excluded.submission, error = excluded.error, failed_at = excluded.failed_at, filed = excluded.filed"#,
+        )
+        .bind(url)
+        .bind(error.accession())
+        .bind(submission)
+        .bind(error.to_string())
+        .bind(Utc::now().to_rfc3339())
+        .bind(filed)
+        .execute(&self.pool)
+        .await;
+    }
+
+    async fn failed_filings(&self) -> Vec<(String, String, Option<String>)> {
+        query_as("SELECT url, submission, filed FROM failed_filings ORDER BY failed_at")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap_or_default()
+    }
+
+    async fn release(&self, url: &str) {
+        let _ = query("DELETE FROM failed_filings WHERE url = $1")
+            .bind(url)
+            .execute(&self.pool)
+            .await;
+    }
+
+    async fn retry_later(
+        &self,
+        url: &str,
+        feed: &str,
+        error: &str,
+        max_attempts: i64,
+        backoff: Duration,
+    ) {
+        let attempts: Option<i64> = query_scalar("SELECT attempts FROM retries WHERE url = $1")
+            .bind(url)
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap_or_default();
+        let attempts = attempts.unwrap_or_default() + 1;
+        if attempts >= max_attempts {
+            eprintln!("Giving up on {} after {} attempts.", url, attempts);
+            let _ = query(
+                r#"INSERT INTO failed_downloads (url, feed, attempts, error, failed_at) VALUES ($1, $2, $3, $4, $5)
+	ON CONFLICT (url) DO UPDATE SET feed = excluded.feed, attempts = excluded.attempts, error = excluded.error, failed_at = excluded.failed_at"#,
+            )
+            .bind(url)
+            .bind(feed)
+            .bind(attempts)
+            .bind(error)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await;
+            self.release_retry(url).await;
+            return;
+        }
+        let next_attempt = Utc::now() + super::backoff(backoff, attempts);
+        let _ = query(
+            r#"INSERT INTO retries (url, feed, attempts, next_attempt, error) VALUES ($1, $2, $3, $4, $5)
+	ON CONFLICT (url) DO UPDATE SET feed = excluded.feed, attempts = excluded.attempts, next_attempt = excluded.next_attempt, error = excluded.error"#,
+        )
+        .bind(url)
+        .bind(feed)
+        .bind(attempts)
+        .bind(next_attempt.to_rfc3339())
+        .bind(error)
+        .execute(&self.pool)
+        .await;
+    }
+
+    async fn due_retries(&self) -> Vec<(String, String)> {
+        query_as("SELECT url, feed FROM retries WHERE next_attempt <= $1 ORDER BY next_attempt")
+            .bind(Utc::now().to_rfc3339())
+            .fetch_all(&self.pool)
+            .await
+            .unwrap_or_default()
+    }
+
+    async fn release_retry(&self, url: &str) {
+        let _ = query("DELETE FROM retries WHERE url = $1")
+            .bind(url)
+            .execute(&self.pool)
+            .await;
+    }
+
+    async fn requeue_failed_downloads(&self) -> Vec<(String, String)> {
+        self.requeue().await.unwrap_or_else(|e| {
+            eprintln!("Failed to requeue failed downloads: {}", e);
+            Vec::new()
+        })
+    }
+
+    async fn migrations(&self) -> Vec<(i64, String)> {
+        query_as("SELECT version, description FROM _sqlx_migrations WHERE success ORDER BY version")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+    use crate::db::tests::exercise;
+
+    // Needs a local server, run it with
+    // POSTGRES_URL=postgres://postgres@localhost/postgres cargo test -- --ignored
+    // Every run works in a throwaway database so it never touches real data.
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server in POSTGRES_URL"]
+    async fn postgres_storage() {
+        let url = std::env::var("POSTGRES_URL").expect("POSTGRES_URL is not set");
+        let admin = PgPool::connect(&url).await.unwrap();
+        let name = format!(
+            "file4_watcher_test_{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        );
+        query(&format!("CREATE DATABASE {}", name))
+            .execute(&admin)
+            .await
+            .unwrap();
+
+        let db = Postgres::connect_with(PgConnectOptions::from_str(&url).unwrap().database(&name))
+            .await
+            .unwrap();
+        exercise(&db).await;
+        db.pool.close().await;
+
+        query(&format!("DROP DATABASE {} WITH (FORCE)", name))
+            .execute(&admin)
+            .await
+            .unwrap();
+    }
+}
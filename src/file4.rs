@@ -1,16 +1,56 @@
-use anyhow::anyhow;
+use std::fmt;
+
 use regex::Regex;
-use roxmltree::{Children, Document, Node};
+use roxmltree::{Document, Node};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
+use crate::submission::{Submission, text};
+
+#[derive(Debug)]
+pub enum File4Error {
+    Submission(&'static str),
+    Xml {
+        accession: String,
+        source: roxmltree::Error,
+    },
+    Missing {
+        accession: String,
+        element: &'static str,
+        path: String,
+    },
+    Invalid {
+        accession: String,
+        element: &'static str,
+        path: String,
+        value: String,
+    },
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct File4 {
     pub id: String,
     pub file_name: String,
+    #[serde(default)]
+    pub document_type: Option<String>,
+    #[serde(default)]
+    pub filed: Option<String>,
+    #[serde(default)]
+    pub original_date: Option<String>,
     pub reporters: Vec<Reporter>,
     pub issuer: Issuer,
     pub non_derivative: Vec<NonDerivative>,
     pub derivative: Vec<Derivative>,
+    #[serde(default)]
+    pub footnotes: Vec<Footnote>,
+    #[serde(default)]
+    pub planned_trade: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Footnote {
+    pub id: String,
+    pub text: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -47,20 +87,27 @@ pub struct NonDerivative {
     pub date: Option<String>,
     pub tx_codes: Option<Vec<TransactionCode>>,
     pub tx_data: Option<TransactionData>,
-    pub owned: f32,
+    pub owned: Decimal,
     pub ownership: Ownership,
+    #[serde(default)]
+    pub footnotes: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Derivative {
     pub title: String,
+    pub conversion_price: Option<Decimal>,
     pub date: Option<String>,
     pub tx_codes: Option<Vec<TransactionCode>>,
     pub count: Option<DerivativeNumber>,
+    pub price: Option<Decimal>,
+    pub exercisable: Option<String>,
+    pub expiration: Option<String>,
     pub underlying: Option<Underlying>,
-    pub price: Option<f32>,
-    pub owned: f32,
+    pub owned: Decimal,
     pub ownership: Ownership,
+    #[serde(default)]
+    pub footnotes: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -94,9 +141,12 @@ pub enum TransactionCode {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TransactionData {
-    pub amount: f32,
+    pub amount: Decimal,
     pub acqired: bool,
-    pub price: f32,
+    // Often only a footnote, e.g. for RSU settlements or gifts
+    pub price: Option<Decimal>,
+    #[serde(default)]
+    pub footnotes: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -107,37 +157,89 @@ pub enum Ownership {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum DerivativeNumber {
-    Acquired(f32),
-    Disposed(f32),
+    Acquired(Decimal),
+    Disposed(Decimal),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Underlying {
     pub title: String,
-    pub price: f32,
+    #[serde(alias = "price")]
+    pub shares: Option<Decimal>,
+    #[serde(default)]
+    pub footnotes: Vec<String>,
+}
+
+// Some(true) for a sentence saying the trade was made under a plan, Some(false) when the
+// only mentions are negated ("was not effected pursuant to a Rule 10b5-1 plan")
+pub fn planned_footnote(footnotes: &[Footnote]) -> Option<bool> {
+    let plan = Regex::new(r"(?i)10b5\s*[-‐‑–]\s*1").unwrap();
+    let negation = Regex::new(r"(?i)\b(not|no|neither|nor|outside|without)\b|n't\b").unwrap();
+    let mut mentioned = false;
+    for i in footnotes {
+        for sentence in i.text.split_inclusive(['.', ';']) {
+            let Some(found) = plan.find(sentence) else {
+                continue;
+            };
+            if !negation.is_match(&sentence[..found.start()]) {
+                return Some(true);
+            }
+            mentioned = true;
+        }
+    }
+    mentioned.then_some(false)
 }
 
 impl File4 {
-    pub fn new(data: String) -> anyhow::Result<Self> {
-        let re = Regex::new(
-            r"(?s)ACCESSION NUMBER:\s+([a-zA-Z0-9-]*).*?<FILENAME>([a-zA-Z0-9-_]*\.xml).*?<XML>\n(.*?)\n<\/XML>",
-        )?;
-        let caps = re.captures(&data).ok_or(anyhow!("No caps found"))?;
-        let id = caps
-            .get(1)
-            .ok_or(anyhow!("No accession number found"))?
-            .as_str()
-            .to_string();
+    pub fn new(data: String) -> Result<Self, File4Error> {
+        let submission =
+            Submission::parse(&data).ok_or(File4Error::Submission("ownership document"))?;
+        Self::from_xml(
+            submission.accession.to_owned(),
+            submission.file_name.to_owned(),
+            submission.filed,
+            submission.xml,
+        )
+    }
+
+    // The primary ownership document on its own, without the SGML submission around it
+    pub fn from_xml(
+        id: String,
+        file_name: String,
+        filed: Option<String>,
+        xml: &str,
+    ) -> Result<Self, File4Error> {
+        let missing = |element, path: String| File4Error::Missing {
+            accession: id.clone(),
+            element,
+            path,
+        };
+
+        let decimal = |element, path: String, text: &str| {
+            text.parse::<Decimal>().map_err(|_| File4Error::Invalid {
+                accession: id.clone(),
+                element,
+                path,
+                value: text.to_owned(),
+            })
+        };
 
-        let file_name = caps
-            .get(2)
-            .ok_or(anyhow!("No file name found"))?
-            .as_str()
-            .to_string();
+        let doc = Document::parse(xml).map_err(|source| File4Error::Xml {
+            accession: id.clone(),
+            source,
+        })?;
 
-        let xml = caps.get(3).ok_or(anyhow!("No xml found"))?.as_str();
+        let document_type = doc
+            .descendants()
+            .find(|n| n.has_tag_name("documentType"))
+            .and_then(|n| n.text())
+            .map(|t| t.trim().to_owned());
 
-        let doc = Document::parse(xml)?;
+        let original_date = doc
+            .descendants()
+            .find(|n| n.has_tag_name("dateOfOriginalSubmission"))
+            .and_then(|n| n.text())
+            .map(|t| t.trim().to_owned());
 
         let mut reporters = Vec::new();
 
@@ -145,75 +247,54 @@ impl File4 {
             .descendants()
             .filter(|n| n.has_tag_name("reportingOwner"))
         {
-            let mut reporter_id = i
+            let path = step(i);
+
+            let reporter_id = i
                 .children()
                 .find(|n| n.has_tag_name("reportingOwnerId"))
-                .ok_or(anyhow!("No reporter id found"))?
-                .children();
+                .ok_or_else(|| missing("reportingOwnerId", format!("{}/reportingOwnerId", path)))?;
 
             let mut relationship = i
                 .children()
                 .find(|n| n.has_tag_name("reportingOwnerRelationship"))
-                .ok_or(anyhow!("No relation found"))?
+                .ok_or_else(|| {
+                    missing(
+                        "reportingOwnerRelationship",
+                        format!("{}/reportingOwnerRelationship", path),
+                    )
+                })?
                 .children();
 
             let mut relations = Vec::new();
 
-            match relationship.find(|n| n.has_tag_name("isDirector")) {
-                Some(n) => match n.text() {
-                    Some(t) => {
-                        if t == "true" {
-                            relations.push(Relation::Director);
-                        } else {
-                            ()
-                        }
-                    }
-                    None => (),
-                },
-                None => (),
+            // xs:boolean, filers use both spellings
+            if let Some("true" | "1") = relationship
+                .find(|n| n.has_tag_name("isDirector"))
+                .and_then(|n| n.text())
+            {
+                relations.push(Relation::Director);
             }
 
-            match relationship.find(|n| n.has_tag_name("isOfficer")) {
-                Some(n) => match n.text() {
-                    Some(t) => {
-                        if t == "true" {
-                            relations.push(Relation::Officer);
-                        } else {
-                            ()
-                        }
-                    }
-                    None => (),
-                },
-                None => (),
-            };
+            if let Some("true" | "1") = relationship
+                .find(|n| n.has_tag_name("isOfficer"))
+                .and_then(|n| n.text())
+            {
+                relations.push(Relation::Officer);
+            }
 
-            match relationship.find(|n| n.has_tag_name("isTenPercentOwner")) {
-                Some(n) => match n.text() {
-                    Some(t) => {
-                        if t == "true" {
-                            relations.push(Relation::Owner);
-                        } else {
-                            ()
-                        }
-                    }
-                    None => (),
-                },
-                None => (),
-            };
+            if let Some("true" | "1") = relationship
+                .find(|n| n.has_tag_name("isTenPercentOwner"))
+                .and_then(|n| n.text())
+            {
+                relations.push(Relation::Owner);
+            }
 
-            match relationship.find(|n| n.has_tag_name("isOther")) {
-                Some(n) => match n.text() {
-                    Some(t) => {
-                        if t == "true" {
-                            relations.push(Relation::Other);
-                        } else {
-                            ()
-                        }
-                    }
-                    None => (),
-                },
-                None => (),
-            };
+            if let Some("true" | "1") = relationship
+                .find(|n| n.has_tag_name("isOther"))
+                .and_then(|n| n.text())
+            {
+                relations.push(Relation::Other);
+            }
 
             let title = relationship
                 .find(|n| n.has_tag_name("officerTitle"))
@@ -224,47 +305,45 @@ impl File4 {
             let relation = Relations { relations, title };
 
             reporters.push(Reporter {
-                cik: reporter_id
-                    .find(|n| n.has_tag_name("rptOwnerCik"))
-                    .ok_or(anyhow!("No reporter cik found"))?
-                    .text()
-                    .ok_or(anyhow!("No reporter cik text found"))?
+                cik: text(reporter_id, "rptOwnerCik")
+                    .ok_or_else(|| {
+                        missing(
+                            "rptOwnerCik",
+                            format!("{}/reportingOwnerId/rptOwnerCik", path),
+                        )
+                    })?
                     .to_owned(),
-                name: reporter_id
-                    .clone()
-                    .find(|n| n.has_tag_name("rptOwnerName"))
-                    .ok_or(anyhow!("No reporter name found"))?
-                    .text()
-                    .ok_or(anyhow!("No reporter name text found"))?
+                name: text(reporter_id, "rptOwnerName")
+                    .ok_or_else(|| {
+                        missing(
+                            "rptOwnerName",
+                            format!("{}/reportingOwnerId/rptOwnerName", path),
+                        )
+                    })?
                     .to_owned(),
                 relation,
             });
         }
 
-        let mut issuer = doc
+        let issuer = doc
             .descendants()
             .find(|n| n.has_tag_name("issuer"))
-            .ok_or(anyhow!("No issuer found"))?
-            .children();
+            .ok_or_else(|| missing("issuer", "issuer".to_owned()))?;
 
         let issuer = Issuer {
-            cik: issuer
-                .find(|n| n.has_tag_name("issuerCik"))
-                .ok_or(anyhow!("No issuer cik found"))?
-                .text()
-                .ok_or(anyhow!("No issuer cik text found"))?
+            cik: text(issuer, "issuerCik")
+                .ok_or_else(|| missing("issuerCik", "issuer/issuerCik".to_owned()))?
                 .to_owned(),
-            name: issuer
-                .find(|n| n.has_tag_name("issuerName"))
-                .ok_or(anyhow!("No issuer name found"))?
-                .text()
-                .ok_or(anyhow!("No issuer name text found"))?
+            name: text(issuer, "issuerName")
+                .ok_or_else(|| missing("issuerName", "issuer/issuerName".to_owned()))?
                 .to_owned(),
-            symbol: issuer
-                .find(|n| n.has_tag_name("issuerTradingSymbol"))
-                .ok_or(anyhow!("No issuer symbol found"))?
-                .text()
-                .ok_or(anyhow!("No issuer symbol text found"))?
+            symbol: text(issuer, "issuerTradingSymbol")
+                .ok_or_else(|| {
+                    missing(
+                        "issuerTradingSymbol",
+                        "issuer/issuerTradingSymbol".to_owned(),
+                    )
+                })?
                 .to_owned(),
         };
 
@@ -274,20 +353,13 @@ impl File4 {
             .descendants()
             .find(|n| n.has_tag_name("nonDerivativeTable"))
         {
-            for i in non_derivatives.children() {
-                if i.tag_name().name() == "" {
-                    continue;
-                }
+            for i in non_derivatives.children().filter(|n| n.is_element()) {
+                let path = format!("nonDerivativeTable/{}", step(i));
 
-                let title = i
-                    .children()
-                    .find(|n| n.has_tag_name("securityTitle"))
-                    .ok_or(anyhow!("No non derivative title found"))?
-                    .children()
-                    .find(|n| n.has_tag_name("value"))
-                    .ok_or(anyhow!("No non derivative title value found"))?
-                    .text()
-                    .ok_or(anyhow!("No non derivative title value text found"))?
+                let title = value(i, "securityTitle")
+                    .ok_or_else(|| {
+                        missing("securityTitle", format!("{}/securityTitle/value", path))
+                    })?
                     .to_owned();
 
                 let date = i
@@ -306,58 +378,66 @@ impl File4 {
                     .unwrap_or_default()
                     .map(|n| n.text())
                     .unwrap_or_default()
-                    .map(|t| TransactionCode::from_str(t))
+                    .map(TransactionCode::from_str)
                     .unwrap_or_default();
 
                 let tx_data = i
                     .children()
                     .find(|n| n.has_tag_name("transactionAmounts"))
-                    .map(|n| TransactionData::from_children(n))
-                    .unwrap_or_default();
+                    .map(|n| {
+                        TransactionData::from_children(
+                            n,
+                            &format!("{}/transactionAmounts", path),
+                            &decimal,
+                        )
+                    })
+                    .transpose()?
+                    .flatten();
 
+                let owned_path = format!(
+                    "{}/postTransactionAmounts/sharesOwnedFollowingTransaction/value",
+                    path
+                );
                 let owned = i
                     .children()
                     .find(|n| n.has_tag_name("postTransactionAmounts"))
-                    .ok_or(anyhow!("No non derivative owned amount found"))?
-                    .children()
-                    .find(|n| n.has_tag_name("sharesOwnedFollowingTransaction"))
-                    .ok_or(anyhow!("No non derivative owned amount 2 found"))?
-                    .children()
-                    .find(|n| n.has_tag_name("value"))
-                    .ok_or(anyhow!("No non derivative owned amount value found"))?
-                    .text()
-                    .ok_or(anyhow!("No non derivative owned amount value text found"))?
-                    .parse()?;
+                    .and_then(|n| value(n, "sharesOwnedFollowingTransaction"))
+                    .ok_or_else(|| {
+                        missing("sharesOwnedFollowingTransaction", owned_path.clone())
+                    })?;
+                let owned = decimal("sharesOwnedFollowingTransaction", owned_path, owned)?;
 
-                let mut ownership = i
+                let nature = i
                     .children()
                     .find(|n| n.has_tag_name("ownershipNature"))
-                    .ok_or(anyhow!("No non derivative ownership found"))?
-                    .children();
+                    .ok_or_else(|| {
+                        missing("ownershipNature", format!("{}/ownershipNature", path))
+                    })?;
 
-                let ownership = match ownership
-                    .find(|n| n.has_tag_name("directOrIndirectOwnership"))
-                    .ok_or(anyhow!("No non derivative ownership bool found"))?
-                    .children()
-                    .find(|n| n.has_tag_name("value"))
-                    .ok_or(anyhow!("No non derivative ownership value found"))?
-                    .text()
-                    .ok_or(anyhow!("No non derivative ownership value text found"))?
-                {
-                    "D" => Ownership::Direct,
-                    _ => Ownership::Indirect(
-                        ownership
-                            .find(|n| n.has_tag_name("natureOfOwnership"))
-                            .ok_or(anyhow!("No non derivative ownership nature found"))?
-                            .children()
-                            .find(|n| n.has_tag_name("value"))
-                            .ok_or(anyhow!("No non derivative ownership nature value found"))?
-                            .text()
-                            .ok_or(anyhow!(
-                                "No non derivative ownership nature value text found"
-                            ))?
-                            .to_owned(),
-                    ),
+                let ownership =
+                    match value(nature, "directOrIndirectOwnership").ok_or_else(|| {
+                        missing(
+                            "directOrIndirectOwnership",
+                            format!("{}/ownershipNature/directOrIndirectOwnership/value", path),
+                        )
+                    })? {
+                        "D" => Ownership::Direct,
+                        _ => Ownership::Indirect(
+                            value(nature, "natureOfOwnership")
+                                .ok_or_else(|| {
+                                    missing(
+                                        "natureOfOwnership",
+                                        format!("{}/ownershipNature/natureOfOwnership/value", path),
+                                    )
+                                })?
+                                .to_owned(),
+                        ),
+                    };
+
+                let footnotes = if tx_data.is_some() {
+                    footnote_ids(i, &["transactionAmounts"])
+                } else {
+                    footnote_ids(i, &[])
                 };
 
                 non_derivative.push(NonDerivative {
@@ -367,6 +447,7 @@ impl File4 {
                     tx_data,
                     owned,
                     ownership,
+                    footnotes,
                 });
             }
         };
@@ -375,31 +456,28 @@ impl File4 {
 
         if let Some(derivatives) = doc
             .descendants()
-            .find(|n| n.has_tag_name("nonDerivativeTable"))
+            .find(|n| n.has_tag_name("derivativeTable"))
         {
-            for i in derivatives.children() {
-                if i.tag_name().name() == "" {
-                    continue;
-                }
+            for i in derivatives.children().filter(|n| n.is_element()) {
+                let path = format!("derivativeTable/{}", step(i));
 
-                let title = i
-                    .children()
-                    .find(|n| n.has_tag_name("securityTitle"))
-                    .ok_or(anyhow!("No derivative title found"))?
-                    .first_child()
-                    .ok_or(anyhow!("No derivative title value found"))?
-                    .text()
-                    .ok_or(anyhow!("No derivative title value text found"))?
+                let title = value(i, "securityTitle")
+                    .ok_or_else(|| {
+                        missing("securityTitle", format!("{}/securityTitle/value", path))
+                    })?
                     .to_owned();
 
-                let date = i
-                    .children()
-                    .find(|n| n.has_tag_name("transactionDate"))
-                    .map(|n| n.first_child())
-                    .unwrap_or_default()
-                    .map(|n| n.text())
-                    .unwrap_or_default()
-                    .map(|t| t.to_owned());
+                let conversion_price = value(i, "conversionOrExercisePrice")
+                    .map(|t| {
+                        decimal(
+                            "conversionOrExercisePrice",
+                            format!("{}/conversionOrExercisePrice/value", path),
+                            t,
+                        )
+                    })
+                    .transpose()?;
+
+                let date = value(i, "transactionDate").map(|t| t.to_owned());
 
                 let tx_codes = i
                     .children()
@@ -408,100 +486,221 @@ impl File4 {
                     .unwrap_or_default()
                     .map(|n| n.text())
                     .unwrap_or_default()
-                    .map(|t| TransactionCode::from_str(t))
+                    .map(TransactionCode::from_str)
                     .unwrap_or_default();
 
                 let count = i
                     .children()
                     .find(|n| n.has_tag_name("transactionAmounts"))
-                    .map(|n| DerivativeNumber::from_children(n.children()))
-                    .unwrap_or_default();
-
-                let underlying = i
-                    .children()
-                    .find(|n| n.has_tag_name("underlyingSecurity"))
-                    .map(|n| Underlying::from_children(n.children()))
-                    .unwrap_or_default();
+                    .map(|n| {
+                        DerivativeNumber::from_children(
+                            n,
+                            &format!("{}/transactionAmounts", path),
+                            &decimal,
+                        )
+                    })
+                    .transpose()?
+                    .flatten();
 
                 let price = i
                     .children()
                     .find(|n| n.has_tag_name("transactionAmounts"))
-                    .map(|n| {
-                        n.children()
-                            .find(|n| n.has_tag_name("transactionPricePerShare"))
+                    .and_then(|n| value(n, "transactionPricePerShare"))
+                    .map(|t| {
+                        decimal(
+                            "transactionPricePerShare",
+                            format!("{}/transactionAmounts/transactionPricePerShare/value", path),
+                            t,
+                        )
                     })
-                    .unwrap_or_default()
-                    .map(|n| n.first_child())
-                    .unwrap_or_default()
-                    .map(|n| n.text())
-                    .unwrap_or_default()
-                    .map(|t| t.parse().ok())
-                    .unwrap_or_default();
+                    .transpose()?;
 
-                let owned = i
+                let exercisable = value(i, "exerciseDate").map(|t| t.to_owned());
+
+                let expiration = value(i, "expirationDate").map(|t| t.to_owned());
+
+                let underlying = i.children().find(|n| n.has_tag_name("underlyingSecurity"));
+                if let Some(shares) = underlying.and_then(|n| value(n, "underlyingSecurityShares"))
+                {
+                    decimal(
+                        "underlyingSecurityShares",
+                        format!("{}/underlyingSecurity/underlyingSecurityShares/value", path),
+                        shares,
+                    )?;
+                }
+                let underlying = underlying.and_then(Underlying::from_children);
+
+                let owned_path = format!(
+                    "{}/postTransactionAmounts/sharesOwnedFollowingTransaction/value",
+                    path
+                );
+                let owned = match i
                     .children()
                     .find(|n| n.has_tag_name("postTransactionAmounts"))
-                    .ok_or(anyhow!("No derivative owned amount found"))?
-                    .children()
-                    .find(|n| n.has_tag_name("sharesOwnedFollowingTransaction"))
-                    .ok_or(anyhow!("No derivative owned amount 2 found"))?
-                    .children()
-                    .find(|n| n.has_tag_name("value"))
-                    .ok_or(anyhow!("No derivative owned amount value found"))?
-                    .text()
-                    .ok_or(anyhow!("No derivative owned amount value text found"))?
-                    .parse()?;
+                {
+                    Some(n) => {
+                        let owned =
+                            value(n, "sharesOwnedFollowingTransaction").ok_or_else(|| {
+                                missing("sharesOwnedFollowingTransaction", owned_path.clone())
+                            })?;
+                        decimal("sharesOwnedFollowingTransaction", owned_path, owned)?
+                    }
+                    // Form 3 holdings only report the position through the underlying security
+                    None => underlying
+                        .as_ref()
+                        .and_then(|u| u.shares)
+                        .ok_or_else(|| missing("postTransactionAmounts", owned_path))?,
+                };
 
-                let mut ownership = i
+                let nature = i
                     .children()
                     .find(|n| n.has_tag_name("ownershipNature"))
-                    .ok_or(anyhow!("No derivative ownership found"))?
-                    .children();
+                    .ok_or_else(|| {
+                        missing("ownershipNature", format!("{}/ownershipNature", path))
+                    })?;
 
-                let ownership = match ownership
-                    .find(|n| n.has_tag_name("directOrIndirectOwnership"))
-                    .ok_or(anyhow!("No derivative ownership bool found"))?
-                    .children()
-                    .find(|n| n.has_tag_name("value"))
-                    .ok_or(anyhow!("No derivative ownership value found"))?
-                    .text()
-                    .ok_or(anyhow!("No derivative ownership value text found"))?
-                {
-                    "D" => Ownership::Direct,
-                    _ => Ownership::Indirect(
-                        ownership
-                            .find(|n| n.has_tag_name("natureOfOwnership"))
-                            .ok_or(anyhow!("No derivative ownership nature found"))?
-                            .first_child()
-                            .ok_or(anyhow!("No derivative ownership nature value found"))?
-                            .text()
-                            .ok_or(anyhow!("No derivative ownership nature value text found"))?
-                            .to_owned(),
-                    ),
+                let ownership =
+                    match value(nature, "directOrIndirectOwnership").ok_or_else(|| {
+                        missing(
+                            "directOrIndirectOwnership",
+                            format!("{}/ownershipNature/directOrIndirectOwnership/value", path),
+                        )
+                    })? {
+                        "D" => Ownership::Direct,
+                        _ => Ownership::Indirect(
+                            value(nature, "natureOfOwnership")
+                                .ok_or_else(|| {
+                                    missing(
+                                        "natureOfOwnership",
+                                        format!("{}/ownershipNature/natureOfOwnership/value", path),
+                                    )
+                                })?
+                                .to_owned(),
+                        ),
+                    };
+
+                let footnotes = if underlying.is_some() {
+                    footnote_ids(i, &["underlyingSecurity"])
+                } else {
+                    footnote_ids(i, &[])
                 };
 
                 derivative.push(Derivative {
                     title,
+                    conversion_price,
                     date,
                     tx_codes,
                     count,
-                    underlying,
                     price,
+                    exercisable,
+                    expiration,
+                    underlying,
                     owned,
                     ownership,
+                    footnotes,
                 });
             }
         };
 
+        let footnotes: Vec<Footnote> = doc
+            .descendants()
+            .find(|n| n.has_tag_name("footnotes"))
+            .map(|n| {
+                n.children()
+                    .filter(|n| n.has_tag_name("footnote"))
+                    .filter_map(|n| {
+                        Some(Footnote {
+                            id: n.attribute("id")?.to_owned(),
+                            text: n
+                                .descendants()
+                                .filter_map(|n| if n.is_text() { n.text() } else { None })
+                                .collect::<String>()
+                                .trim()
+                                .to_owned(),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // The checkbox wins whenever it was filed, older forms only mention the plan in a footnote
+        let planned_trade = doc
+            .descendants()
+            .find(|n| n.has_tag_name("aff10b5One"))
+            .and_then(|n| n.text())
+            .map(|t| matches!(t.trim(), "1" | "true"))
+            .or_else(|| planned_footnote(&footnotes));
+
         Ok(Self {
             id,
             file_name,
+            document_type,
+            filed,
+            original_date,
             reporters,
             issuer,
             non_derivative,
             derivative,
+            footnotes,
+            planned_trade,
         })
     }
+
+    pub fn is_amendment(&self) -> bool {
+        self.document_type
+            .as_deref()
+            .is_some_and(|t| t.ends_with("/A"))
+    }
+
+    pub fn footnote(&self, id: &str) -> Option<&Footnote> {
+        self.footnotes.iter().find(|f| f.id == id)
+    }
+}
+
+impl File4Error {
+    pub fn accession(&self) -> Option<&str> {
+        match self {
+            Self::Submission(_) => None,
+            Self::Xml { accession, .. }
+            | Self::Missing { accession, .. }
+            | Self::Invalid { accession, .. } => Some(accession),
+        }
+    }
+}
+
+impl fmt::Display for File4Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Submission(what) => write!(f, "No {} found in submission", what),
+            Self::Xml { accession, source } => {
+                write!(f, "{}: invalid ownership xml: {}", accession, source)
+            }
+            Self::Missing {
+                accession,
+                element,
+                path,
+            } => write!(f, "{}: missing <{}> at {}", accession, element, path),
+            Self::Invalid {
+                accession,
+                element,
+                path,
+                value,
+            } => write!(
+                f,
+                "{}: invalid <{}> value {:?} at {}",
+                accession, element, value, path
+            ),
+        }
+    }
+}
+
+impl std::error::Error for File4Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Xml { source, .. } => Some(source),
+            _ => None,
+        }
+    }
 }
 
 impl TransactionCode {
@@ -537,88 +736,421 @@ impl TransactionCode {
 }
 
 impl TransactionData {
-    pub fn from_children(parrent: Node) -> Option<Self> {
-        let Some(amount) = parrent
-            .children()
-            .find(|n| n.has_tag_name("transactionShares"))
-            .map(|n| n.children().find(|n| n.has_tag_name("value")))
-            .unwrap_or_default()
-            .map(|n| n.text())
-            .unwrap_or_default()
-            .map(|t| t.parse().ok())
-            .unwrap_or_default()
-        else {
-            return None;
-        };
-
-        let Some(acqired) = parrent
-            .children()
-            .find(|n| n.has_tag_name("transactionAcquiredDisposedCode"))
-            .map(|n| n.children().find(|n| n.has_tag_name("value")))
-            .unwrap_or_default()
-            .map(|n| n.text())
-            .unwrap_or_default()
-            .map(|t| t == "A")
-        else {
-            return None;
+    pub fn from_children(
+        parrent: Node,
+        path: &str,
+        decimal: &impl Fn(&'static str, String, &str) -> Result<Decimal, File4Error>,
+    ) -> Result<Option<Self>, File4Error> {
+        let (Some(amount), Some(acqired)) = (
+            value(parrent, "transactionShares"),
+            value(parrent, "transactionAcquiredDisposedCode"),
+        ) else {
+            return Ok(None);
         };
+        let amount = decimal(
+            "transactionShares",
+            format!("{}/transactionShares/value", path),
+            amount,
+        )?;
 
-        let Some(price) = parrent
-            .children()
-            .find(|n| n.has_tag_name("transactionPricePerShare"))
-            .map(|n| n.children().find(|n| n.has_tag_name("value")))
-            .unwrap_or_default()
-            .map(|n| n.text())
-            .unwrap_or_default()
-            .map(|t| t.parse().ok())
-            .unwrap_or_default()
-        else {
-            return None;
-        };
+        let price = value(parrent, "transactionPricePerShare")
+            .map(|t| {
+                decimal(
+                    "transactionPricePerShare",
+                    format!("{}/transactionPricePerShare/value", path),
+                    t,
+                )
+            })
+            .transpose()?;
 
-        Some(Self {
+        Ok(Some(Self {
             amount,
-            acqired,
+            acqired: acqired == "A",
             price,
-        })
+            footnotes: footnote_ids(parrent, &[]),
+        }))
     }
 }
 
 impl DerivativeNumber {
-    pub fn from_children(mut children: Children) -> Option<Self> {
-        let count = children
-            .find(|n| n.has_tag_name("transactionShares"))?
-            .first_child()?
-            .text()?
-            .parse()
-            .ok()?;
-        Some(
-            match children
-                .find(|n| n.has_tag_name("transactionAcquiredDisposedCode"))?
-                .first_child()?
-                .text()?
-            {
-                "A" => Self::Acquired(count),
-                _ => Self::Disposed(count),
-            },
-        )
+    pub fn from_children(
+        parrent: Node,
+        path: &str,
+        decimal: &impl Fn(&'static str, String, &str) -> Result<Decimal, File4Error>,
+    ) -> Result<Option<Self>, File4Error> {
+        let (Some(count), Some(code)) = (
+            value(parrent, "transactionShares"),
+            value(parrent, "transactionAcquiredDisposedCode"),
+        ) else {
+            return Ok(None);
+        };
+        let count = decimal(
+            "transactionShares",
+            format!("{}/transactionShares/value", path),
+            count,
+        )?;
+        Ok(Some(match code {
+            "A" => Self::Acquired(count),
+            _ => Self::Disposed(count),
+        }))
     }
 }
 
 impl Underlying {
-    pub fn from_children(mut children: Children) -> Option<Self> {
+    pub fn from_children(parrent: Node) -> Option<Self> {
         Some(Self {
-            title: children
-                .find(|n| n.has_tag_name("underlyingSecurityTitle"))?
-                .first_child()?
-                .text()?
-                .to_owned(),
-            price: children
-                .find(|n| n.has_tag_name("underlyingSecurityShares"))?
-                .first_child()?
-                .text()?
-                .parse()
-                .ok()?,
+            title: value(parrent, "underlyingSecurityTitle")?.to_owned(),
+            shares: value(parrent, "underlyingSecurityShares").and_then(|t| t.parse().ok()),
+            footnotes: footnote_ids(parrent, &[]),
         })
     }
 }
+
+// An XPath step like nonDerivativeHolding[1], counted per tag name and 1-based,
+// prev_siblings starts at the node itself
+fn step(node: Node) -> String {
+    let name = node.tag_name().name();
+    let n = node
+        .prev_siblings()
+        .filter(|n| n.has_tag_name(name))
+        .count();
+    format!("{}[{}]", name, n)
+}
+
+fn value<'a, 'input>(parrent: Node<'a, 'input>, tag: &str) -> Option<&'a str> {
+    parrent
+        .children()
+        .find(|n| n.has_tag_name(tag))?
+        .children()
+        .find(|n| n.has_tag_name("value"))?
+        .text()
+        .map(|t| t.trim())
+}
+
+fn footnote_ids(parrent: Node, exclude: &[&str]) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for n in parrent
+        .descendants()
+        .filter(|n| n.has_tag_name("footnoteId"))
+    {
+        if n.ancestors()
+            .take_while(|a| *a != parrent)
+            .any(|a| exclude.iter().any(|e| a.has_tag_name(*e)))
+        {
+            continue;
+        }
+        let Some(id) = n.attribute("id") else {
+            continue;
+        };
+        if !out.iter().any(|i| i == id) {
+            out.push(id.to_owned());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::dec;
+
+    use super::*;
+
+    const DERIVATIVE: &str = include_str!("../tests/fixtures/form4_derivative.txt");
+    const FORM3: &str = include_str!("../tests/fixtures/form3.txt");
+    const FORM5: &str = include_str!("../tests/fixtures/form5.txt");
+    const AMENDMENT: &str = include_str!("../tests/fixtures/form4_amendment.txt");
+    const MALFORMED: &str = include_str!("../tests/fixtures/form4_malformed.txt");
+
+    #[test]
+    fn parses_derivative_table() {
+        let file4 = File4::new(DERIVATIVE.to_owned()).unwrap();
+
+        assert_eq!(file4.id, "0001209191-24-031842");
+        assert_eq!(file4.planned_trade, Some(false));
+        assert_eq!(file4.non_derivative.len(), 2);
+        assert_eq!(file4.derivative.len(), 2);
+
+        let rsu = &file4.derivative[0];
+        assert_eq!(rsu.title, "Restricted Stock Units");
+        assert_eq!(rsu.conversion_price, None);
+        assert_eq!(rsu.date.as_deref(), Some("2024-03-01"));
+        assert!(matches!(
+            rsu.tx_codes.as_deref(),
+            Some([TransactionCode::M])
+        ));
+        assert!(matches!(rsu.count, Some(DerivativeNumber::Disposed(n)) if n == dec!(12500)));
+        // RSUs report the price only through a footnote
+        assert_eq!(rsu.price, None);
+        assert_eq!(rsu.exercisable, None);
+        assert_eq!(rsu.expiration, None);
+        let underlying = rsu.underlying.as_ref().unwrap();
+        assert_eq!(underlying.title, "Class A Common Stock");
+        assert_eq!(underlying.shares, Some(dec!(12500)));
+        assert_eq!(rsu.owned, dec!(62500));
+        assert!(matches!(rsu.ownership, Ownership::Direct));
+    }
+
+    #[test]
+    fn parses_form3_holdings() {
+        let file3 = File4::new(FORM3.to_owned()).unwrap();
+
+        assert_eq!(file3.document_type.as_deref(), Some("3"));
+        assert!(matches!(
+            file3.reporters[0].relation.relations[..],
+            [Relation::Director]
+        ));
+        assert_eq!(file3.non_derivative.len(), 1);
+        assert!(file3.non_derivative[0].tx_data.is_none());
+        assert_eq!(file3.non_derivative[0].owned, dec!(25000));
+
+        let option = &file3.derivative[0];
+        assert_eq!(option.conversion_price, Some(dec!(21.08)));
+        assert_eq!(option.expiration.as_deref(), Some("2034-03-17"));
+        assert_eq!(option.owned, dec!(40000));
+        assert_eq!(option.footnotes, ["F1"]);
+    }
+
+    #[test]
+    fn parses_form5() {
+        let file5 = File4::new(FORM5.to_owned()).unwrap();
+
+        assert_eq!(file5.document_type.as_deref(), Some("5"));
+        assert_eq!(file5.filed.as_deref(), Some("2024-02-12"));
+        assert!(!file5.is_amendment());
+        assert!(matches!(
+            file5.reporters[0].relation.relations[..],
+            [Relation::Director]
+        ));
+
+        // A late Form 4 purchase next to the year's gifts, the timeliness flag doesn't change the rows
+        assert_eq!(file5.non_derivative.len(), 3);
+        let purchase = &file5.non_derivative[0];
+        assert!(matches!(
+            purchase.tx_codes.as_deref(),
+            Some([TransactionCode::P])
+        ));
+        let data = purchase.tx_data.as_ref().unwrap();
+        assert!(data.acqired);
+        assert_eq!(data.price, Some(dec!(18.215)));
+        assert_eq!(data.footnotes, ["F1"]);
+        // The price of a gift is only a footnote, the transaction itself stays
+        let data = file5.non_derivative[1].tx_data.as_ref().unwrap();
+        assert!(!data.acqired);
+        assert_eq!(data.amount, dec!(2000));
+        assert_eq!(data.price, None);
+        assert_eq!(data.footnotes, ["F3"]);
+        let gift = &file5.non_derivative[2];
+        assert!(matches!(
+            gift.tx_codes.as_deref(),
+            Some([TransactionCode::G])
+        ));
+        assert!(matches!(&gift.ownership, Ownership::Indirect(n) if n == "By Roe Family Trust"));
+        assert_eq!(gift.owned, dec!(12000));
+
+        let option = &file5.derivative[0];
+        assert!(matches!(option.count, Some(DerivativeNumber::Acquired(n)) if n == dec!(5000)));
+        assert_eq!(option.conversion_price, Some(dec!(17.92)));
+        assert_eq!(option.exercisable, None);
+    }
+
+    #[test]
+    fn parses_amendment() {
+        let file4 = File4::new(DERIVATIVE.to_owned()).unwrap();
+        assert_eq!(file4.document_type.as_deref(), Some("4"));
+        assert_eq!(file4.filed.as_deref(), Some("2024-03-05"));
+        assert!(!file4.is_amendment());
+
+        let amendment = File4::new(AMENDMENT.to_owned()).unwrap();
+        assert_eq!(amendment.document_type.as_deref(), Some("4/A"));
+        assert!(amendment.is_amendment());
+        assert_eq!(amendment.original_date, file4.filed);
+    }
+
+    #[test]
+    fn detects_planned_trade() {
+        assert_eq!(
+            File4::new(AMENDMENT.to_owned()).unwrap().planned_trade,
+            Some(true)
+        );
+        assert_eq!(
+            File4::new(DERIVATIVE.to_owned()).unwrap().planned_trade,
+            Some(false)
+        );
+        assert_eq!(File4::new(FORM5.to_owned()).unwrap().planned_trade, None);
+
+        let footnote = |text: &str| {
+            [Footnote {
+                id: "F1".to_owned(),
+                text: text.to_owned(),
+            }]
+        };
+        assert_eq!(
+            planned_footnote(&footnote(
+                "Shares withheld to cover taxes. Effected pursuant to a Rule 10b5-1 trading plan adopted on November 14, 2023."
+            )),
+            Some(true)
+        );
+        assert_eq!(
+            planned_footnote(&footnote(
+                "The sale was not effected pursuant to a Rule 10b5-1 trading plan."
+            )),
+            Some(false)
+        );
+        assert_eq!(
+            planned_footnote(&footnote("Shares withheld to cover taxes.")),
+            None
+        );
+
+        // An explicit unchecked box beats a footnote mentioning a plan
+        let file4 = File4::new(DERIVATIVE.replace(
+            "for no consideration.",
+            "for no consideration. Effected pursuant to a Rule 10b5-1 trading plan adopted on November 14, 2023.",
+        ))
+        .unwrap();
+        assert_eq!(file4.planned_trade, Some(false));
+    }
+
+    #[test]
+    fn round_trips_exact_amounts() {
+        let file4 = File4::new(AMENDMENT.to_owned()).unwrap();
+        let sale = file4.non_derivative[0].tx_data.as_ref().unwrap();
+        assert_eq!(sale.amount, dec!(4817.3362));
+        assert_eq!(sale.price, Some(dec!(187.4523)));
+
+        let json = serde_json::to_string(&file4).unwrap();
+        assert!(json.contains(r#""owned":"182522.6638""#));
+
+        let file4: File4 = serde_json::from_str(&json).unwrap();
+        assert_eq!(file4.non_derivative[0].owned, dec!(182522.6638));
+    }
+
+    #[test]
+    fn parses_primary_document() {
+        let (_, xml) = DERIVATIVE.split_once("<XML>\n").unwrap();
+        let (xml, _) = xml.split_once("\n</XML>").unwrap();
+        let file4 = File4::from_xml(
+            "0001209191-24-031842".to_owned(),
+            "doc4.xml".to_owned(),
+            Some("2024-03-05".to_owned()),
+            xml,
+        )
+        .unwrap();
+
+        assert_eq!(file4.id, "0001209191-24-031842");
+        assert_eq!(file4.non_derivative.len(), 2);
+        assert_eq!(file4.derivative.len(), 2);
+        assert_eq!(file4.footnotes.len(), 3);
+    }
+
+    #[test]
+    fn names_missing_element() {
+        let err = File4::new(MALFORMED.to_owned()).unwrap_err();
+
+        assert!(matches!(
+            &err,
+            File4Error::Missing { accession, element: "sharesOwnedFollowingTransaction", path }
+                if accession == "0001209191-24-031877"
+                    && path == "nonDerivativeTable/nonDerivativeHolding[1]/postTransactionAmounts/sharesOwnedFollowingTransaction/value"
+        ));
+        assert_eq!(err.accession(), Some("0001209191-24-031877"));
+
+        // The holding comes after a transaction but is still the first holding
+        let err = File4::new(DERIVATIVE.replace("<value>42000</value>", "")).unwrap_err();
+        assert!(matches!(
+            &err,
+            File4Error::Missing { path, .. }
+                if path == "nonDerivativeTable/nonDerivativeHolding[1]/postTransactionAmounts/sharesOwnedFollowingTransaction/value"
+        ));
+
+        let err = File4::new(DERIVATIVE.replace("reportingOwnerRelationship>", "relationship>"))
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            File4Error::Missing { element: "reportingOwnerRelationship", path, .. }
+                if path == "reportingOwner[1]/reportingOwnerRelationship"
+        ));
+
+        let err =
+            File4::new(DERIVATIVE.replacen("<value>12500</value>", "<value>12,500</value>", 1))
+                .unwrap_err();
+        assert!(matches!(
+            &err,
+            File4Error::Invalid { element: "transactionShares", path, value, .. }
+                if path == "nonDerivativeTable/nonDerivativeTransaction[1]/transactionAmounts/transactionShares/value"
+                    && value == "12,500"
+        ));
+
+        let err = File4::new(DERIVATIVE.replace(
+            "<value>0</value>\n                    <footnoteId id=\"F3\"/>",
+            "<value>$0.00</value>",
+        ))
+        .unwrap_err();
+        assert!(matches!(
+            &err,
+            File4Error::Invalid {
+                element: "transactionPricePerShare",
+                ..
+            }
+        ));
+
+        let err = File4::new(DERIVATIVE.replace("<value>14.37</value>", "<value>$14.37</value>"))
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            File4Error::Invalid { element: "conversionOrExercisePrice", path, value, .. }
+                if path == "derivativeTable/derivativeHolding[1]/conversionOrExercisePrice/value"
+                    && value == "$14.37"
+        ));
+
+        let err = File4::new(DERIVATIVE.replace("<value>80000</value>", "<value>80,000</value>"))
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            File4Error::Invalid { element: "underlyingSecurityShares", path, .. }
+                if path == "derivativeTable/derivativeHolding[1]/underlyingSecurity/underlyingSecurityShares/value"
+        ));
+    }
+
+    #[test]
+    fn links_footnotes() {
+        let file4 = File4::new(DERIVATIVE.to_owned()).unwrap();
+
+        assert_eq!(file4.footnotes.len(), 3);
+        assert!(
+            file4
+                .footnote("F1")
+                .unwrap()
+                .text
+                .starts_with("Each restricted stock unit")
+        );
+
+        let rsu = &file4.derivative[0];
+        assert_eq!(rsu.footnotes, ["F1", "F2"]);
+        assert!(rsu.underlying.as_ref().unwrap().footnotes.is_empty());
+        assert!(file4.derivative[1].footnotes.is_empty());
+        assert!(file4.non_derivative[0].footnotes.is_empty());
+        assert_eq!(
+            file4.non_derivative[0].tx_data.as_ref().unwrap().footnotes,
+            ["F3"]
+        );
+    }
+
+    #[test]
+    fn parses_derivative_holding() {
+        let file4 = File4::new(DERIVATIVE.to_owned()).unwrap();
+
+        let option = &file4.derivative[1];
+        assert_eq!(option.title, "Stock Option (Right to Buy)");
+        assert_eq!(option.conversion_price, Some(dec!(14.37)));
+        assert_eq!(option.date, None);
+        assert!(option.tx_codes.is_none());
+        assert!(option.count.is_none());
+        assert_eq!(option.exercisable.as_deref(), Some("2022-06-15"));
+        assert_eq!(option.expiration.as_deref(), Some("2031-06-14"));
+        assert_eq!(
+            option.underlying.as_ref().and_then(|u| u.shares),
+            Some(dec!(80000))
+        );
+        assert_eq!(option.owned, dec!(80000));
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::anyhow;
+use roxmltree::Document;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::submission::{Submission, iso_date, text};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct File144 {
+    pub id: String,
+    pub file_name: String,
+    pub filed: Option<String>,
+    pub issuer_cik: String,
+    pub issuer_name: String,
+    pub seller_cik: Option<String>,
+    pub seller_name: String,
+    pub relations: Vec<String>,
+    pub sales: Vec<ProposedSale>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProposedSale {
+    pub title: String,
+    pub broker: Option<String>,
+    pub shares: Decimal,
+    pub market_value: Decimal,
+    pub outstanding: Option<Decimal>,
+    pub date: Option<String>,
+    pub exchange: Option<String>,
+}
+
+impl File144 {
+    pub fn new(data: String) -> anyhow::Result<Self> {
+        let submission = Submission::parse(&data).ok_or(anyhow!("No xml notice found"))?;
+        let id = submission.accession.to_owned();
+        let file_name = submission.file_name.to_owned();
+        let filed = submission.filed;
+
+        let doc = Document::parse(submission.xml)?;
+
+        let issuer = doc
+            .descendants()
+            .find(|n| n.has_tag_name("issuerInfo"))
+            .ok_or(anyhow!("No issuer found"))?;
+
+        let issuer_cik = text(issuer, "issuerCik")
+            .ok_or(anyhow!("No issuer cik found"))?
+            .to_owned();
+
+        let issuer_name = text(issuer, "issuerName")
+            .ok_or(anyhow!("No issuer name found"))?
+            .to_owned();
+
+        let seller_name = text(
+            issuer,
+            "nameOfPersonForWhoseAccountTheSecuritiesAreToBeSold",
+        )
+        .ok_or(anyhow!("No seller name found"))?
+        .to_owned();
+
+        let relations = issuer
+            .descendants()
+            .filter(|n| n.has_tag_name("relationshipToIssuer"))
+            .filter_map(|n| n.text())
+            .map(|t| t.trim().to_owned())
+            .collect();
+
+        let seller_cik = doc
+            .descendants()
+            .find(|n| n.has_tag_name("filerCredentials"))
+            .and_then(|n| text(n, "cik"))
+            .map(|t| t.to_owned());
+
+        let mut sales = Vec::new();
+
+        for i in doc
+            .descendants()
+            .filter(|n| n.has_tag_name("securitiesInformation"))
+        {
+            let title = text(i, "securitiesClassTitle")
+                .ok_or(anyhow!("No securities class title found"))?
+                .to_owned();
+
+            let broker = i
+                .children()
+                .find(|n| n.has_tag_name("brokerOrMarketmakerDetails"))
+                .and_then(|n| text(n, "name"))
+                .map(|t| t.to_owned());
+
+            let shares = text(i, "noOfUnitsSold")
+                .ok_or(anyhow!("No units sold found"))?
+                .parse()?;
+
+            let market_value = text(i, "aggregateMarketValue")
+                .ok_or(anyhow!("No aggregate market value found"))?
+                .parse()?;
+
+            let outstanding = text(i, "noOfUnitsOutstanding").and_then(|t| t.parse().ok());
+
+            let date = text(i, "approxSaleDate").map(iso_date);
+
+            let exchange = text(i, "securitiesExchangeName").map(|t| t.to_owned());
+
+            sales.push(ProposedSale {
+                title,
+                broker,
+                shares,
+                market_value,
+                outstanding,
+                date,
+                exchange,
+            });
+        }
+
+        Ok(Self {
+            id,
+            file_name,
+            filed,
+            issuer_cik,
+            issuer_name,
+            seller_cik,
+            seller_name,
+            relations,
+            sales,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::dec;
+
+    use super::*;
+
+    const FORM144: &str = include_str!("../tests/fixtures/form144.txt");
+
+    #[test]
+    fn parses_proposed_sale() {
+        let file144 = File144::new(FORM144.to_owned()).unwrap();
+
+        assert_eq!(file144.id, "0001971857-24-000188");
+        assert_eq!(file144.filed.as_deref(), Some("2024-04-01"));
+        assert_eq!(file144.issuer_cik, "0001654321");
+        assert_eq!(file144.seller_cik.as_deref(), Some("0001771234"));
+        assert_eq!(file144.seller_name, "Doe Jane");
+        assert_eq!(file144.relations, ["Officer"]);
+
+        let sale = &file144.sales[0];
+        assert_eq!(sale.title, "Class A Common Stock");
+        assert_eq!(
+            sale.broker.as_deref(),
+            Some("Morgan Stanley Smith Barney LLC")
+        );
+        assert_eq!(sale.shares, dec!(12500));
+        assert_eq!(sale.market_value, dec!(431250));
+        assert_eq!(sale.date.as_deref(), Some("2024-04-02"));
+        assert_eq!(sale.exchange.as_deref(), Some("NASDAQ"));
+    }
+}
@@ -0,0 +1,125 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use reqwest::{Client, Response, StatusCode, header::RETRY_AFTER};
+use tokio::{
+    sync::Mutex,
+    time::{Instant, sleep},
+};
+
+const RETRIES: u32 = 5;
+const BACKOFF: Duration = Duration::from_secs(5);
+
+// Every request to sec.gov goes through one of these, so the feed poller and the
+// filing fetcher share the same client, User-Agent and request budget.
+#[derive(Clone)]
+pub struct Http {
+    client: Client,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    rate: f64,
+    last: Instant,
+    paused_until: Instant,
+}
+
+impl Http {
+    pub fn new(user_agent: &str, per_second: u32) -> Self {
+        let now = Instant::now();
+        Self {
+            client: Client::builder().user_agent(user_agent).build().unwrap(),
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: per_second as f64,
+                rate: per_second as f64,
+                last: now,
+                paused_until: now,
+            })),
+        }
+    }
+
+    // Anything but a 200 is an error, after backing off and retrying on 429 and 403
+    pub async fn get(&self, url: &str) -> anyhow::Result<String> {
+        for attempt in 0..RETRIES {
+            self.take().await;
+            let res = self.client.get(url).send().await?;
+            match res.status() {
+                StatusCode::OK => return Ok(res.text().await?),
+                StatusCode::TOO_MANY_REQUESTS | StatusCode::FORBIDDEN => {
+                    let wait = retry_after(&res).unwrap_or(BACKOFF * 2u32.pow(attempt));
+                    eprintln!(
+                        "SEC answered {} for {}, backing off for {}s.",
+                        res.status(),
+                        url,
+                        wait.as_secs()
+                    );
+                    self.pause(wait).await;
+                }
+                status => return Err(anyhow!("HTTP {}", status)),
+            }
+        }
+        Err(anyhow!("Still rate limited after {} attempts", RETRIES))
+    }
+
+    async fn take(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().await;
+                let now = Instant::now();
+                if bucket.paused_until > now {
+                    bucket.paused_until - now
+                } else {
+                    let elapsed = now.duration_since(bucket.last).as_secs_f64();
+                    bucket.tokens = (bucket.tokens + elapsed * bucket.rate).min(bucket.rate);
+                    bucket.last = now;
+                    if bucket.tokens >= 1.0 {
+                        bucket.tokens -= 1.0;
+                        return;
+                    }
+                    Duration::from_secs_f64((1.0 - bucket.tokens) / bucket.rate)
+                }
+            };
+            sleep(wait).await;
+        }
+    }
+
+    async fn pause(&self, wait: Duration) {
+        let mut bucket = self.bucket.lock().await;
+        bucket.paused_until = bucket.paused_until.max(Instant::now() + wait);
+        bucket.tokens = 0.0;
+    }
+}
+
+fn retry_after(res: &Response) -> Option<Duration> {
+    let secs = res
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn limits_request_rate() {
+        let http = Http::new("test@example.com", 10);
+        let start = Instant::now();
+        for _ in 0..30 {
+            http.take().await;
+        }
+        // The first 10 are a burst, the other 20 wait for the bucket to refill
+        assert!(start.elapsed() >= Duration::from_secs(2));
+        assert!(start.elapsed() < Duration::from_millis(2100));
+
+        http.pause(Duration::from_secs(60)).await;
+        let paused = Instant::now();
+        http.take().await;
+        assert!(paused.elapsed() >= Duration::from_secs(60));
+    }
+}
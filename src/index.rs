@@ -0,0 +1,242 @@
+use std::collections::HashSet;
+
+use chrono::{Datelike, Local, NaiveDate};
+use serde_json::Value;
+
+use crate::http::Http;
+
+pub fn daily_url(date: NaiveDate) -> String {
+    format!(
+        "https://www.sec.gov/Archives/edgar/daily-index/{}/QTR{}/form.{}.idx",
+        date.year(),
+        date.month0() / 3 + 1,
+        date.format("%Y%m%d")
+    )
+}
+
+pub fn quarter_url(year: i32, quarter: u32) -> String {
+    format!(
+        "https://www.sec.gov/Archives/edgar/full-index/{}/QTR{}/form.idx",
+        year, quarter
+    )
+}
+
+pub fn quarters(from: NaiveDate, to: NaiveDate) -> Vec<(i32, u32)> {
+    let mut out = Vec::new();
+    let (mut year, mut quarter) = (from.year(), from.month0() / 3 + 1);
+    while (year, quarter) <= (to.year(), to.month0() / 3 + 1) {
+        out.push((year, quarter));
+        (year, quarter) = if quarter == 4 {
+            (year + 1, 1)
+        } else {
+            (year, quarter + 1)
+        };
+    }
+    out
+}
+
+// The daily index writes the filing date as 20240305 and the full index as 2024-03-05
+pub fn filed_between(data: &str, from: NaiveDate, to: NaiveDate) -> String {
+    data.lines()
+        .filter(|line| {
+            let Some(date) = line.split_whitespace().rev().nth(1) else {
+                return false;
+            };
+            let Ok(date) = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .or_else(|_| NaiveDate::parse_from_str(date, "%Y%m%d"))
+            else {
+                return false;
+            };
+            from <= date && date <= to
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Lines look like `4           Doe Jane      1771234     20240305    edgar/data/1771234/0001209191-24-031842.txt`,
+// and the same filing is listed once for every filer on it.
+pub fn filings(data: &str, forms: &[String]) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    let mut seen = HashSet::new();
+    for line in data.lines() {
+        if !forms.iter().any(|f| line.starts_with(f)) {
+            continue;
+        }
+        let Some(file) = line.split_whitespace().last() else {
+            continue;
+        };
+        if !file.starts_with("edgar/") {
+            continue;
+        }
+        // The same filing is listed once per filer, a quarter has hundreds of thousands of lines
+        if !seen.insert(file.rsplit('/').next().unwrap_or(file)) {
+            continue;
+        }
+        out.push(format!("https://www.sec.gov/Archives/{}", file));
+    }
+    out
+}
+
+// Full index links end in <cik>/0001209191-24-031842.txt next to the 000120919124031842
+// folder, feed links (-index.htm, or .txt once the watcher rewrote them) and primary
+// documents already sit inside it
+pub fn accession(url: &str) -> String {
+    let mut segments = url.rsplit('/');
+    let name = segments.next().unwrap_or(url);
+    if name.ends_with(".xml")
+        && let Some(folder) = segments.next()
+        && folder.len() == 18
+    {
+        return format!("{}-{}-{}", &folder[..10], &folder[10..12], &folder[12..]);
+    }
+    name.trim_end_matches("-index.htm")
+        .trim_end_matches(".txt")
+        .to_owned()
+}
+
+pub fn folder(url: &str) -> Option<String> {
+    let (base, _) = url.rsplit_once('/')?;
+    let folder = accession(url).replace('-', "");
+    if base.rsplit('/').next() == Some(folder.as_str()) {
+        return Some(format!("{}/", base));
+    }
+    Some(format!("{}/{}/", base, folder))
+}
+
+// The filing index lists every document in the filing, ownership filings carry a
+// single xml document next to the rendered html and the index pages.
+pub fn primary_document(data: &str) -> Option<String> {
+    let index: Value = serde_json::from_str(data).ok()?;
+    let item = index["directory"]["item"].as_array()?.iter().find(|i| {
+        i["name"]
+            .as_str()
+            .is_some_and(|n| n.ends_with(".xml") && n != "FilingSummary.xml")
+    })?;
+    Some(item["name"].as_str()?.to_owned())
+}
+
+pub fn headers_url(url: &str) -> Option<String> {
+    Some(format!(
+        "{}{}-index-headers.html",
+        folder(url)?,
+        accession(url)
+    ))
+}
+
+pub async fn since(http: &Http, from: NaiveDate, forms: &[String]) -> Vec<String> {
+    let mut out = Vec::new();
+    let today = Local::now().date_naive();
+    for date in from.iter_days().take_while(|d| *d <= today) {
+        // Weekends and holidays have no daily index
+        let Ok(data) = http.get(&daily_url(date)).await else {
+            continue;
+        };
+        out.extend(filings(&data, forms));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::submission::filed_as_of;
+
+    #[test]
+    fn picks_form_entries() {
+        let data = "Form Type   Company Name                  CIK         Date Filed  File Name
+---------------------------------------------------------------------------------------------
+3           Roe Richard                   2019876     20240328    edgar/data/2019876/0001104659-24-041277.txt
+4           Doe Jane                      1771234     20240305    edgar/data/1771234/0001209191-24-031842.txt
+4           Example Robotics, Inc.        1654321     20240305    edgar/data/1654321/0001209191-24-031842.txt
+4/A         Doe Jane                      1771234     20240305    edgar/data/1771234/0001209191-24-031901.txt
+424B2       Example Robotics, Inc.        1654321     20240305    edgar/data/1654321/0001654321-24-000011.txt
+";
+        let forms = vec!["4 ".to_owned(), "4/A ".to_owned()];
+
+        assert_eq!(
+            filings(data, &forms),
+            [
+                "https://www.sec.gov/Archives/edgar/data/1771234/0001209191-24-031842.txt",
+                "https://www.sec.gov/Archives/edgar/data/1771234/0001209191-24-031901.txt",
+            ]
+        );
+        assert_eq!(
+            daily_url(NaiveDate::from_ymd_opt(2024, 3, 5).unwrap()),
+            "https://www.sec.gov/Archives/edgar/daily-index/2024/QTR1/form.20240305.idx"
+        );
+    }
+
+    #[test]
+    fn resolves_primary_document() {
+        let url = "https://www.sec.gov/Archives/edgar/data/1771234/0001209191-24-031842.txt";
+        let data = r#"{"directory":{"item":[
+            {"last-modified":"2024-03-05 18:30:15","name":"0001209191-24-031842-index-headers.html","type":"text.gif","size":""},
+            {"last-modified":"2024-03-05 18:30:15","name":"0001209191-24-031842.txt","type":"text.gif","size":"12 KB"},
+            {"last-modified":"2024-03-05 18:30:15","name":"doc4.xml","type":"text.gif","size":"8 KB"}
+        ],"name":"/Archives/edgar/data/1771234/000120919124031842","parent-dir":"/Archives/edgar/data/1771234/"}}"#;
+
+        assert_eq!(
+            folder(url).as_deref(),
+            Some("https://www.sec.gov/Archives/edgar/data/1771234/000120919124031842/")
+        );
+        assert_eq!(primary_document(data).as_deref(), Some("doc4.xml"));
+        assert_eq!(
+            headers_url(url).as_deref(),
+            Some(
+                "https://www.sec.gov/Archives/edgar/data/1771234/000120919124031842/0001209191-24-031842-index-headers.html"
+            )
+        );
+        // Accepted after 17:30 on the 5th, filed as of the 6th
+        let headers = "<pre>\n&lt;ACCEPTANCE-DATETIME&gt;20240305183015\nACCESSION NUMBER:\t\t0001209191-24-031842\nCONFORMED SUBMISSION TYPE:\t4\nPUBLIC DOCUMENT COUNT:\t\t1\nCONFORMED PERIOD OF REPORT:\t20240301\nFILED AS OF DATE:\t\t20240306\n";
+        assert_eq!(filed_as_of(headers).as_deref(), Some("2024-03-06"));
+        assert_eq!(
+            accession(
+                "https://www.sec.gov/Archives/edgar/data/1771234/000120919124031842/doc4.xml"
+            ),
+            "0001209191-24-031842"
+        );
+
+        // Feed links point into the folder already, the watcher swaps -index.htm for .txt
+        for url in [
+            "https://www.sec.gov/Archives/edgar/data/1771234/000120919124031842/0001209191-24-031842-index.htm",
+            "https://www.sec.gov/Archives/edgar/data/1771234/000120919124031842/0001209191-24-031842.txt",
+        ] {
+            assert_eq!(
+                folder(url).as_deref(),
+                Some("https://www.sec.gov/Archives/edgar/data/1771234/000120919124031842/")
+            );
+            assert_eq!(
+                headers_url(url).as_deref(),
+                Some(
+                    "https://www.sec.gov/Archives/edgar/data/1771234/000120919124031842/0001209191-24-031842-index-headers.html"
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn filters_full_index_by_date() {
+        let data = "Form Type   Company Name                  CIK         Date Filed  File Name
+---------------------------------------------------------------------------------------------
+4           Doe Jane                      1771234     2023-12-29  edgar/data/1771234/0001209191-23-098765.txt
+4           Doe Jane                      1771234     2024-01-02  edgar/data/1771234/0001209191-24-000123.txt
+SC 13D      Example Robotics, Inc.        1654321     2024-01-03  edgar/data/1654321/0000921895-24-000007.txt
+";
+        let from = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2024, 12, 31).unwrap();
+        let forms = vec!["4 ".to_owned(), "4/A ".to_owned()];
+
+        assert_eq!(
+            filings(&filed_between(data, from, to), &forms),
+            ["https://www.sec.gov/Archives/edgar/data/1771234/0001209191-24-000123.txt"]
+        );
+        assert_eq!(
+            quarters(NaiveDate::from_ymd_opt(2023, 11, 5).unwrap(), to),
+            [(2023, 4), (2024, 1), (2024, 2), (2024, 3), (2024, 4)]
+        );
+        assert_eq!(
+            quarter_url(2024, 1),
+            "https://www.sec.gov/Archives/edgar/full-index/2024/QTR1/form.idx"
+        );
+    }
+}
@@ -1,131 +1,862 @@
-use std::{env::var, time::Duration};
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use reqwest::{Client, Method};
-use serde_json::{json, to_string};
-use sqlx::{SqlitePool, query};
-use tokio::{fs::OpenOptions, time::sleep};
+use anyhow::anyhow;
+use chrono::{Local, NaiveDate};
+use clap::Parser;
+use futures_util::{StreamExt, future::select_all, stream};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{Value, json, to_string, to_string_pretty};
+use tokio::time::interval;
 
-use crate::{file4::File4, watcher::Watcher};
+use crate::{
+    cli::{Cli, Command, DbCommand, Format, Output},
+    config::Config,
+    db::{Db, Storage, TransactionFilter},
+    file4::{DerivativeNumber, File4, File4Error, Ownership, Relation, TransactionCode},
+    form144::File144,
+    http::Http,
+    schedule13::Schedule13,
+    watcher::Watcher,
+};
 
+mod cli;
+mod config;
+mod dataset;
+mod db;
 mod file4;
+mod form144;
+mod http;
+mod index;
+mod schedule13;
+mod submission;
 mod watcher;
 
-const PERCENTAGE: f32 = 20.0;
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Feed {
+    Ownership,
+    Notice,
+    Schedule,
+}
+
+impl Feed {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Ownership => "ownership",
+            Self::Notice => "notice",
+            Self::Schedule => "schedule",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ownership" => Some(Self::Ownership),
+            "notice" => Some(Self::Notice),
+            "schedule" => Some(Self::Schedule),
+            _ => None,
+        }
+    }
+}
+
+struct Download {
+    url: String,
+    source: String,
+    feed: Feed,
+    content: anyhow::Result<String>,
+    // Only known apart from the content for primary documents
+    filed: Option<String>,
+    file4: Option<Result<File4, File4Error>>,
+}
+
+async fn download(http: Http, url: String, feed: Feed, primary: bool) -> Download {
+    if primary && matches!(feed, Feed::Ownership) {
+        match primary_xml(&http, &url).await {
+            Ok((source, content, filed, file4)) => {
+                return Download {
+                    url,
+                    source,
+                    feed,
+                    content: Ok(content),
+                    filed,
+                    file4: Some(file4),
+                };
+            }
+            Err(e) => eprintln!(
+                "No primary document for {}, fetching the submission: {}",
+                url, e
+            ),
+        }
+    }
+    let content = http.get(&url).await;
+    let file4 = match (&content, feed) {
+        (Ok(content), Feed::Ownership) => Some(File4::new(content.clone())),
+        _ => None,
+    };
+    Download {
+        source: url.clone(),
+        url,
+        feed,
+        content,
+        filed: None,
+        file4,
+    }
+}
+
+async fn primary_xml(
+    http: &Http,
+    url: &str,
+) -> anyhow::Result<(String, String, Option<String>, Result<File4, File4Error>)> {
+    let folder = index::folder(url).ok_or(anyhow!("Not a filing url"))?;
+    let name = index::primary_document(&http.get(&format!("{}index.json", folder)).await?)
+        .ok_or(anyhow!("No xml document in the filing index"))?;
+    // Without the headers filed stays unknown rather than guessed from the acceptance time
+    let filed = match index::headers_url(url) {
+        Some(headers) => http
+            .get(&headers)
+            .await
+            .ok()
+            .and_then(|h| submission::filed_as_of(&h)),
+        None => None,
+    };
+    let source = format!("{}{}", folder, name);
+    let xml = http.get(&source).await?;
+    let file4 = File4::from_xml(index::accession(url), name, filed.clone(), &xml);
+    Ok((source, xml, filed, file4))
+}
+
+fn received(res: Option<Vec<String>>, feed: Feed) -> Vec<(String, Feed)> {
+    let res = res.unwrap_or_default();
+    println!("Received {} new filings.", res.len());
+    res.into_iter().map(|i| (i, feed)).collect()
+}
 
 #[tokio::main]
 async fn main() {
-    let webhook: String = var("WEBHOOK").expect("No WEBHOOK env set.");
-    {
-        OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open("db.sqlite")
-            .await
-            .unwrap();
+    let command = Cli::parse().command.unwrap_or(Command::Watch);
+    // Parsing a local file needs neither the config nor a database
+    if let Command::Parse { file, format } = &command {
+        parse(file, *format);
+        return;
     }
 
-    let pool = SqlitePool::connect("sqlite://db.sqlite").await.unwrap();
+    let config = Config::load(&command).unwrap_or_else(|e| {
+        eprintln!("{:#}", e);
+        std::process::exit(1);
+    });
+    let db = db::connect(&config.storage.url).await;
 
-    query(
-        r#"CREATE TABLE IF NOT EXISTS file4s ("id"	TEXT NOT NULL UNIQUE,
-	"file4"	TEXT NOT NULL,
-	PRIMARY KEY("id"))"#,
+    match command {
+        Command::Watch => watch(&config, db, http(&config)).await,
+        Command::Backfill { from, to } => {
+            let to = to.unwrap_or_else(|| Local::now().date_naive());
+            backfill(db.as_ref(), &http(&config), &config, from, to).await;
+        }
+        Command::Parse { .. } => unreachable!(),
+        Command::Reprocess => reprocess(db.as_ref()).await,
+        Command::Import { zip } => import(db.as_ref(), &zip).await,
+        Command::Query { filter } => query(db.as_ref(), filter.into_filter(100)).await,
+        Command::Export {
+            filter,
+            format,
+            output,
+        } => export(db.as_ref(), filter.into_filter(i64::MAX), format, output).await,
+        Command::Db {
+            command: DbCommand::Migrate,
+        } => migrate(db.as_ref()).await,
+    }
+}
+
+fn http(config: &Config) -> Http {
+    Http::new(
+        config
+            .http
+            .user_agent
+            .as_deref()
+            .expect("Config::validate checks the user agent"),
+        config.http.rate_limit,
     )
-    .execute(&pool)
-    .await
-    .unwrap();
+}
 
-    let mut watcher = Watcher::new(
-        "https://www.sec.gov/cgi-bin/browse-edgar?action=getcurrent&CIK=&type=4&company=&dateb=&owner=include&start=0&count=100&output=atom".to_string(),
-        Duration::from_secs(30),
-    );
+async fn watch(config: &Config, db: Db, http: Http) {
+    let webhooks = &config.notify.webhooks;
+
+    let mut watchers: Vec<(Watcher, Feed)> = config
+        .feeds
+        .iter()
+        .map(|i| {
+            let forms: Vec<&str> = i.forms.iter().map(|f| f.as_str()).collect();
+            let watcher = Watcher::new(
+                i.url.clone(),
+                &forms,
+                i.pages,
+                Duration::from_secs(i.interval_secs),
+                db.clone(),
+                http.clone(),
+            );
+            (watcher, i.kind)
+        })
+        .collect();
+
+    let mut retries = interval(config.retry_interval());
 
     loop {
-        if let Ok(Some(res)) = watcher.wait().await {
-            println!("Received {} new file4s.", res.len());
-            for i in res {
-                sleep(Duration::from_millis(250)).await;
-                let client = Client::new();
-                let req = client
-                    .request(Method::GET, &i)
-                    .header("User-Agent", "some@email.com");
-                let Ok(req) = req.build() else {
+        let filings = tokio::select! {
+            ((res, feed), _, _) = select_all(
+                watchers
+                    .iter_mut()
+                    .map(|(w, feed)| Box::pin(async move { (w.wait().await, *feed) })),
+            ) => received(res, feed),
+            _ = retries.tick() => db.due_retries()
+                .await
+                .into_iter()
+                .filter_map(|(url, feed)| Some((url, Feed::from_name(&feed)?)))
+                .collect(),
+        };
+        // Downloads run concurrently but come back in feed order, so alerts keep their order
+        let mut downloads = stream::iter(filings)
+            .map(|(i, feed)| tokio::spawn(download(http.clone(), i, feed, config.http.primary_xml)))
+            .buffered(config.http.workers);
+        while let Some(res) = downloads.next().await {
+            let Ok(Download {
+                url: i,
+                source,
+                feed,
+                content,
+                filed,
+                file4,
+            }) = res
+            else {
+                continue;
+            };
+            let content = match content {
+                Ok(content) => content,
+                Err(e) => {
+                    eprintln!("Failed to fetch {}: {}", i, e);
+                    db.retry_later(
+                        &i,
+                        feed.name(),
+                        &e.to_string(),
+                        config.retries.attempts,
+                        config.retry_backoff(),
+                    )
+                    .await;
                     continue;
-                };
-                let Ok(res) = client.execute(req).await else {
+                }
+            };
+            db.release_retry(&i).await;
+            match feed {
+                Feed::Ownership => (),
+                Feed::Notice => {
+                    notice(&i, content, db.as_ref(), config).await;
                     continue;
-                };
-                let Ok(content) = res.text().await else {
+                }
+                Feed::Schedule => {
+                    schedule(&i, content, db.as_ref()).await;
                     continue;
+                }
+            }
+            let Some(file4) = file4 else {
+                continue;
+            };
+            let file4 = match file4 {
+                Ok(file4) => file4,
+                Err(e) => {
+                    eprintln!("Failed to parse {}: {}", source, e);
+                    db.quarantine(&source, &e, content, filed.as_deref()).await;
+                    continue;
+                }
+            };
+            if !db.insert_file4(&file4).await {
+                continue;
+            }
+            let planned = file4.planned_trade.unwrap_or_default();
+            if planned && config.thresholds.suppress_planned {
+                continue;
+            }
+            // A document without owners still alerts, its folder is listed under the issuer too
+            let folder_cik = file4
+                .reporters
+                .first()
+                .map_or(&file4.issuer.cik, |r| &r.cik);
+            let reporters = match &file4.reporters[..] {
+                [] => "An unnamed reporter".to_owned(),
+                [r] => format!(
+                    "[{}](https://www.sec.gov/edgar/browse/?CIK={})",
+                    r.name, r.cik
+                ),
+                r => format!("{} people", r.len()),
+            };
+            for i in &file4.non_derivative {
+                let Some(data) = &i.tx_data else {
+                    continue;
+                };
+                let before = if data.acqired {
+                    i.owned - data.amount
+                } else {
+                    i.owned + data.amount
                 };
-                let file4 = File4::new(content).unwrap();
-                if query("INSERT INTO file4s (id, file4) VALUES (?, ?)")
-                    .bind(file4.id.clone())
-                    .bind(to_string(&file4).unwrap())
-                    .execute(&pool)
-                    .await
-                    .is_err()
+                // A position opened from nothing counts as the whole holding
+                let percentage = (data.amount * Decimal::ONE_HUNDRED)
+                    .checked_div(before)
+                    .unwrap_or(Decimal::ONE_HUNDRED)
+                    .round_dp(2);
+                if percentage >= config.thresholds.percentage {
+                    let footnotes: Vec<_> = i
+                        .footnotes
+                        .iter()
+                        .chain(data.footnotes.iter())
+                        .filter_map(|id| file4.footnote(id))
+                        .map(|f| {
+                            json!({
+                                "name": f.id,
+                                "value": f.text.chars().take(1024).collect::<String>()
+                            })
+                        })
+                        .collect();
+                    let data = json!({
+                        "embeds": [
+                            {
+                                "author": {
+                                    "name": "100% correct market advise xD",
+                                    "icon_url": "https://www.descargarstickers.com/src_img/2020/05/856404.png"
+                                },
+                                "footer": {
+                                    "text": "Don't trust this if you don't know what you're doing"
+                                },
+                                "color": if data.acqired { 65280 } else { 16711680 },
+                                "title": format!("{}% {}{}{}!", percentage, if file4.is_amendment() { "amended " } else { "" }, if planned { "planned " } else { "" }, if data.acqired { "buy" } else { "sale" }),
+                                "url": format!("https://www.sec.gov/Archives/edgar/data/{}/{}/xslF345X05/{}", folder_cik, file4.id.replace("-", ""), file4.file_name.clone()),
+                                "description": format!(
+                                    "{} {} {}({}%) of [{}({})](https://www.sec.gov/edgar/browse/?CIK={}){}",
+                                    reporters,
+                                    if data.acqired { "bought" } else { "sold" },
+                                    data.amount,
+                                    percentage,
+                                    file4.issuer.name,
+                                    file4.issuer.symbol,
+                                    file4.issuer.cik,
+                                    if let Some(date) = &i.date {
+                                        format!(" {}.", date)
+                                    } else {
+                                        ".".to_owned()
+                                    }
+                                ),
+                                "fields": footnotes
+                            }
+                        ]
+                    });
+
+                    send(webhooks, &data).await;
+                }
+            }
+        }
+    }
+}
+
+async fn notice(url: &str, content: String, db: &dyn Storage, config: &Config) {
+    let file144 = match File144::new(content) {
+        Ok(file144) => file144,
+        Err(e) => {
+            eprintln!("Failed to parse notice {}: {}", url, e);
+            return;
+        }
+    };
+    if !db.insert_file144(&file144).await {
+        return;
+    };
+    for i in &file144.sales {
+        if i.market_value < config.thresholds.notice_value {
+            continue;
+        }
+        let data = json!({
+            "embeds": [
                 {
+                    "author": {
+                        "name": "100% correct market advise xD",
+                        "icon_url": "https://www.descargarstickers.com/src_img/2020/05/856404.png"
+                    },
+                    "footer": {
+                        "text": "Don't trust this if you don't know what you're doing"
+                    },
+                    "color": 16753920,
+                    "title": format!("${} proposed sale!", i.market_value),
+                    "url": format!("https://www.sec.gov/Archives/edgar/data/{}/{}/xsl144X01/{}", file144.issuer_cik, file144.id.replace("-", ""), file144.file_name.clone()),
+                    "description": format!(
+                        "{} plans to sell {} {} of [{}](https://www.sec.gov/edgar/browse/?CIK={}){}{}",
+                        if let Some(cik) = &file144.seller_cik {
+                            format!("[{}](https://www.sec.gov/edgar/browse/?CIK={})", file144.seller_name, cik)
+                        } else {
+                            file144.seller_name.clone()
+                        },
+                        i.shares,
+                        i.title,
+                        file144.issuer_name,
+                        file144.issuer_cik,
+                        if let Some(broker) = &i.broker {
+                            format!(" through {}", broker)
+                        } else {
+                            "".to_owned()
+                        },
+                        if let Some(date) = &i.date {
+                            format!(" around {}.", date)
+                        } else {
+                            ".".to_owned()
+                        }
+                    )
+                }
+            ]
+        });
+
+        send(&config.notify.webhooks, &data).await;
+    }
+}
+
+async fn send(webhooks: &[String], data: &Value) {
+    let client = Client::new();
+    for i in webhooks {
+        let _ = client
+            .execute(client.post(i).json(data).build().unwrap())
+            .await;
+    }
+}
+
+async fn schedule(url: &str, content: String, db: &dyn Storage) {
+    let schedule13 = match Schedule13::new(content) {
+        Ok(schedule13) => schedule13,
+        Err(e) => {
+            eprintln!("Failed to parse schedule {}: {}", url, e);
+            return;
+        }
+    };
+    db.insert_schedule13(&schedule13).await;
+}
+
+async fn reprocess(db: &dyn Storage) {
+    let failed = db.failed_filings().await;
+    let mut fixed = 0;
+    for (url, submission, filed) in &failed {
+        let file4 = if url.ends_with(".xml") {
+            File4::from_xml(
+                index::accession(url),
+                url.rsplit('/').next().unwrap_or(url).to_owned(),
+                filed.clone(),
+                submission,
+            )
+        } else {
+            File4::new(submission.clone())
+        };
+        match file4 {
+            Ok(file4) => {
+                db.insert_file4(&file4).await;
+                db.release(url).await;
+                fixed += 1;
+            }
+            Err(e) => {
+                println!("Still failing {}: {}", url, e);
+                db.quarantine(url, &e, submission.clone(), filed.as_deref())
+                    .await;
+            }
+        }
+    }
+    println!(
+        "Reprocessed {} failed filings, {} now parse.",
+        failed.len(),
+        fixed
+    );
+
+    // Downloads that ran out of retries go back to the watcher, it fetches them on its next tick
+    let requeued = db.requeue_failed_downloads().await;
+    for (url, error) in &requeued {
+        println!("Requeued {} (last error: {})", url, error);
+    }
+    println!("Requeued {} failed downloads.", requeued.len());
+}
+
+// Anything already stored or quarantined is skipped, so an interrupted backfill
+// can simply be started again with the same range.
+async fn backfill(db: &dyn Storage, http: &Http, config: &Config, from: NaiveDate, to: NaiveDate) {
+    let forms = vec!["4 ".to_owned(), "4/A ".to_owned()];
+    let mut stored = 0;
+    for (year, quarter) in index::quarters(from, to) {
+        let Ok(data) = http.get(&index::quarter_url(year, quarter)).await else {
+            eprintln!("No full index for {} QTR{}", year, quarter);
+            continue;
+        };
+        let urls = index::filings(&index::filed_between(&data, from, to), &forms);
+        println!("{} QTR{}: {} filings.", year, quarter, urls.len());
+        let mut missing = Vec::new();
+        for url in urls {
+            if !db.known(&index::accession(&url)).await {
+                missing.push((url, Feed::Ownership));
+            }
+        }
+        let mut downloads = stream::iter(missing)
+            .map(|(url, feed)| {
+                tokio::spawn(download(http.clone(), url, feed, config.http.primary_xml))
+            })
+            .buffered(config.http.workers);
+        while let Some(res) = downloads.next().await {
+            let Ok(Download {
+                url,
+                source,
+                content,
+                filed,
+                file4,
+                ..
+            }) = res
+            else {
+                continue;
+            };
+            let content = match content {
+                Ok(content) => content,
+                Err(e) => {
+                    eprintln!("Failed to fetch {}: {}", url, e);
                     continue;
-                };
-                for i in file4.non_derivative {
-                    let Some(data) = i.tx_data else {
-                        continue;
-                    };
-                    let percentage = if data.acqired {
-                        data.amount / ((i.owned - data.amount) / 100.0)
-                    } else {
-                        data.amount / ((i.owned + data.amount) / 100.0)
-                    };
-                    if percentage >= PERCENTAGE {
-                        let data = json!({
-                            "embeds": [
-                                {
-                                    "author": {
-                                        "name": "100% correct market advise xD",
-                                        "icon_url": "https://www.descargarstickers.com/src_img/2020/05/856404.png"
-                                    },
-                                    "footer": {
-                                        "text": "Don't trust this if you don't know what you're doing"
-                                    },
-                                    "color": if data.acqired { 65280 } else { 16711680 },
-                                    "title": format!("{}% {}!", percentage, if data.acqired { "buy" } else { "sale" }),
-                                    "url": format!("https://www.sec.gov/Archives/edgar/data/{}/{}/xslF345X05/{}", file4.reporters[0].cik, file4.id.replace("-", ""), file4.file_name.clone()),
-                                    "description": format!(
-                                        "{} {} {}({}%) of [{}({})](https://www.sec.gov/edgar/browse/?CIK={}){}",
-                                        if file4.reporters.len() == 1 {
-                                            format!(
-                                                "[{}](https://www.sec.gov/edgar/browse/?CIK={})",
-                                                file4.reporters[0].name,
-                                                file4.reporters[0].cik
-                                            )
-                                        } else {
-                                            format!("{} people", file4.reporters.len())
-                                        },
-                                        if data.acqired { "bought" } else { "sold" },
-                                        data.amount,
-                                        percentage,
-                                        file4.issuer.name,
-                                        file4.issuer.symbol,
-                                        file4.issuer.cik,
-                                        if let Some(date) = i.date {
-                                            format!(" {}.", date)
-                                        } else {
-                                            ".".to_owned()
-                                        }
-                                    )
-                                }
-                            ]
-                        });
-
-                        let client = Client::new();
-
-                        let _ = client
-                            .execute(client.post(webhook.clone()).json(&data).build().unwrap())
-                            .await;
+                }
+            };
+            let Some(file4) = file4 else {
+                continue;
+            };
+            match file4 {
+                Ok(file4) => {
+                    if db.insert_file4(&file4).await {
+                        stored += 1;
                     }
                 }
+                Err(e) => {
+                    eprintln!("Failed to parse {}: {}", source, e);
+                    db.quarantine(&source, &e, content, filed.as_deref()).await;
+                }
+            }
+        }
+    }
+    println!("Backfilled {} filings between {} and {}.", stored, from, to);
+}
+
+async fn import(db: &dyn Storage, path: &str) {
+    let filings = match dataset::read(path) {
+        Ok(filings) => filings,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path, e);
+            return;
+        }
+    };
+    let mut stored = 0;
+    for file4 in &filings {
+        if db.insert_file4(file4).await {
+            stored += 1;
+        }
+    }
+    println!(
+        "Imported {} of {} filings from {}.",
+        stored,
+        filings.len(),
+        path
+    );
+}
+
+fn parse(path: &Path, format: Output) {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Can't read {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    // A bare xml has no header to take the accession number from, the file name stands in
+    let file4 = if path.extension().is_some_and(|e| e == "xml") {
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        let id = path.file_stem().unwrap().to_string_lossy().to_string();
+        File4::from_xml(id, name, None, &content)
+    } else {
+        File4::new(content)
+    };
+    match file4 {
+        Ok(file4) => match format {
+            Output::Table => print!("{}", table(&file4)),
+            Output::Json => println!("{}", to_string_pretty(&file4).unwrap()),
+        },
+        Err(e) => {
+            eprintln!("Failed to parse {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn table(file4: &File4) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{}  form {}  {}{}{}{}",
+        file4.id,
+        file4.document_type.as_deref().unwrap_or("?"),
+        file4.file_name,
+        file4
+            .filed
+            .as_ref()
+            .map(|d| format!("  filed {}", d))
+            .unwrap_or_default(),
+        file4
+            .original_date
+            .as_ref()
+            .map(|d| format!("  amends {}", d))
+            .unwrap_or_default(),
+        if file4.planned_trade.unwrap_or_default() {
+            "  planned trade"
+        } else {
+            ""
+        }
+    );
+    let _ = writeln!(
+        out,
+        "Issuer    {} ({}, CIK {})",
+        file4.issuer.name, file4.issuer.symbol, file4.issuer.cik
+    );
+    for i in &file4.reporters {
+        let relations: Vec<String> = i
+            .relation
+            .relations
+            .iter()
+            .map(|r| match (r, &i.relation.title) {
+                (Relation::Officer, Some(title)) => format!("Officer ({})", title),
+                _ => format!("{:?}", r),
+            })
+            .collect();
+        let _ = writeln!(
+            out,
+            "Reporter  {} (CIK {}) {}",
+            i.name,
+            i.cik,
+            relations.join(", ")
+        );
+    }
+
+    if !file4.non_derivative.is_empty() {
+        let _ = writeln!(
+            out,
+            "\nNon-derivative\n{:<10}  {:<5}  {:>14}  {:>12}  {:>14}  {:<9}  TITLE",
+            "DATE", "CODE", "SHARES", "PRICE", "OWNED", "OWNERSHIP"
+        );
+        for i in &file4.non_derivative {
+            let (shares, price, footnotes) = match &i.tx_data {
+                Some(data) => (
+                    format!("{}{}", if data.acqired { "+" } else { "-" }, data.amount),
+                    data.price.map(|p| p.to_string()).unwrap_or_default(),
+                    [i.footnotes.as_slice(), data.footnotes.as_slice()].concat(),
+                ),
+                None => ("".to_owned(), "".to_owned(), i.footnotes.clone()),
+            };
+            let _ = writeln!(
+                out,
+                "{:<10}  {:<5}  {:>14}  {:>12}  {:>14}  {:<9}  {}{}{}",
+                i.date.as_deref().unwrap_or_default(),
+                codes(&i.tx_codes),
+                shares,
+                price,
+                i.owned,
+                ownership(&i.ownership).0,
+                i.title,
+                ownership(&i.ownership).1,
+                refs(&footnotes)
+            );
+        }
+    }
+
+    if !file4.derivative.is_empty() {
+        let _ = writeln!(
+            out,
+            "\nDerivative\n{:<10}  {:<5}  {:>14}  {:>12}  {:<10}  {:<10}  {:>14}  {:>14}  {:<9}  TITLE",
+            "DATE",
+            "CODE",
+            "SHARES",
+            "PRICE",
+            "EXERCISE",
+            "EXPIRES",
+            "UNDERLYING",
+            "OWNED",
+            "OWNERSHIP"
+        );
+        for i in &file4.derivative {
+            let shares = match &i.count {
+                Some(DerivativeNumber::Acquired(n)) => format!("+{}", n),
+                Some(DerivativeNumber::Disposed(n)) => format!("-{}", n),
+                None => "".to_owned(),
+            };
+            let underlying = i.underlying.as_ref().and_then(|u| u.shares);
+            let mut footnotes = i.footnotes.clone();
+            if let Some(u) = &i.underlying {
+                footnotes.extend(u.footnotes.iter().cloned());
             }
+            let _ = writeln!(
+                out,
+                "{:<10}  {:<5}  {:>14}  {:>12}  {:<10}  {:<10}  {:>14}  {:>14}  {:<9}  {}{}{}",
+                i.date.as_deref().unwrap_or_default(),
+                codes(&i.tx_codes),
+                shares,
+                i.price.map(|p| p.to_string()).unwrap_or_default(),
+                i.exercisable.as_deref().unwrap_or_default(),
+                i.expiration.as_deref().unwrap_or_default(),
+                underlying.map(|u| u.to_string()).unwrap_or_default(),
+                i.owned,
+                ownership(&i.ownership).0,
+                i.title,
+                ownership(&i.ownership).1,
+                refs(&footnotes)
+            );
         }
     }
+
+    if !file4.footnotes.is_empty() {
+        out.push_str("\nFootnotes\n");
+        for i in &file4.footnotes {
+            let _ = writeln!(out, "{:<4}  {}", i.id, i.text);
+        }
+    }
+    out
+}
+
+fn codes(codes: &Option<Vec<TransactionCode>>) -> String {
+    codes.iter().flatten().map(|c| format!("{:?}", c)).collect()
+}
+
+// The nature of an indirect holding goes after the title so the columns stay aligned
+fn ownership(ownership: &Ownership) -> (&'static str, String) {
+    match ownership {
+        Ownership::Direct => ("D", "".to_owned()),
+        Ownership::Indirect(nature) => ("I", format!(" ({})", nature)),
+    }
+}
+
+fn refs(footnotes: &[String]) -> String {
+    if footnotes.is_empty() {
+        "".to_owned()
+    } else {
+        format!(" [{}]", footnotes.join(","))
+    }
+}
+
+async fn query(db: &dyn Storage, filter: TransactionFilter) {
+    let transactions = db.transactions(&filter).await;
+    println!(
+        "{:<10}  {:<5}  {:>14}  {:>12}  {:>14}  {:<6}  {:<30}  REPORTER",
+        "DATE", "CODE", "SHARES", "PRICE", "OWNED", "SYMBOL", "ISSUER"
+    );
+    for i in &transactions {
+        let shares = match (i.shares, i.acquired) {
+            (Some(shares), Some(false)) => format!("-{}", shares),
+            (Some(shares), _) => shares.to_string(),
+            (None, _) => "".to_owned(),
+        };
+        println!(
+            "{:<10}  {:<5}  {:>14}  {:>12}  {:>14}  {:<6}  {:<30}  {}",
+            i.date.as_deref().unwrap_or_default(),
+            i.codes.as_deref().unwrap_or_default(),
+            shares,
+            i.price.map(|p| p.to_string()).unwrap_or_default(),
+            i.owned,
+            i.symbol.as_deref().unwrap_or_default(),
+            i.issuer_name.chars().take(30).collect::<String>(),
+            i.reporter_name.as_deref().unwrap_or_default()
+        );
+    }
+    println!("{} transactions.", transactions.len());
+}
+
+async fn export(
+    db: &dyn Storage,
+    filter: TransactionFilter,
+    format: Format,
+    output: Option<PathBuf>,
+) {
+    let transactions = db.transactions(&filter).await;
+    let mut data = String::new();
+    match format {
+        Format::Csv => {
+            data.push_str("filing_id,filed,issuer_cik,issuer_name,symbol,reporter_cik,reporter_name,title,date,codes,shares,acquired,price,owned\n");
+            for i in &transactions {
+                let row = [
+                    Some(i.filing_id.clone()),
+                    i.filed.clone(),
+                    Some(i.issuer_cik.clone()),
+                    Some(i.issuer_name.clone()),
+                    i.symbol.clone(),
+                    i.reporter_cik.clone(),
+                    i.reporter_name.clone(),
+                    Some(i.title.clone()),
+                    i.date.clone(),
+                    i.codes.clone(),
+                    i.shares.map(|s| s.to_string()),
+                    i.acquired.map(|a| a.to_string()),
+                    i.price.map(|p| p.to_string()),
+                    Some(i.owned.to_string()),
+                ];
+                let row: Vec<String> = row
+                    .iter()
+                    .map(|f| csv_field(f.as_deref().unwrap_or_default()))
+                    .collect();
+                data.push_str(&row.join(","));
+                data.push('\n');
+            }
+        }
+        Format::Json => {
+            for i in &transactions {
+                data.push_str(&to_string(i).unwrap());
+                data.push('\n');
+            }
+        }
+    }
+    match output {
+        Some(path) => match fs::write(&path, data) {
+            Ok(()) => println!(
+                "Exported {} transactions to {}.",
+                transactions.len(),
+                path.display()
+            ),
+            Err(e) => {
+                eprintln!("Can't write {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => print!("{}", data),
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+async fn migrate(db: &dyn Storage) {
+    // Connecting already ran anything pending
+    for (version, description) in db.migrations().await {
+        println!("{:04} {}", version, description);
+    }
+    println!("Database is up to date.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_table() {
+        let file4 =
+            File4::new(include_str!("../tests/fixtures/form4_derivative.txt").to_owned()).unwrap();
+        let table = table(&file4);
+        assert!(table.starts_with("0001209191-24-031842  form 4  doc4.xml  filed 2024-03-05\n"));
+        assert!(table.contains("Issuer    Example Robotics, Inc. (EXRB, CIK 0001654321)"));
+        assert!(table.contains("2024-03-01  M              +12500             0          187340  D          Class A Common Stock [F3]"));
+        assert!(table.contains("\nDerivative\n"));
+        assert!(table.contains("\nFootnotes\nF1    "));
+    }
 }
@@ -0,0 +1,133 @@
+use anyhow::anyhow;
+use roxmltree::{Document, Node};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::submission::{Submission, iso_date};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Schedule13 {
+    pub id: String,
+    pub file_name: String,
+    pub form_type: String,
+    pub filed: Option<String>,
+    pub issuer_cik: String,
+    pub issuer_name: String,
+    pub class_title: Option<String>,
+    pub event_date: Option<String>,
+    pub owners: Vec<BeneficialOwner>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BeneficialOwner {
+    pub name: String,
+    pub cik: Option<String>,
+    pub shares: Option<Decimal>,
+    pub percent: Option<Decimal>,
+}
+
+impl Schedule13 {
+    pub fn new(data: String) -> anyhow::Result<Self> {
+        let submission = Submission::parse(&data).ok_or(anyhow!("No xml schedule found"))?;
+        let id = submission.accession.to_owned();
+        let form_type = submission
+            .form_type
+            .ok_or(anyhow!("No submission type found"))?
+            .to_owned();
+        let file_name = submission.file_name.to_owned();
+        let filed = submission.filed;
+
+        let doc = Document::parse(submission.xml)?;
+        let root = doc.root_element();
+
+        // The 13D and 13G schemas name the same cover page items slightly differently,
+        // so elements are matched on their lowercased local name.
+        let issuer_cik = find(root, |t| t == "issuercik")
+            .ok_or(anyhow!("No issuer cik found"))?
+            .to_owned();
+
+        let issuer_name = find(root, |t| t == "issuername")
+            .ok_or(anyhow!("No issuer name found"))?
+            .to_owned();
+
+        let class_title = find(root, |t| t == "securitiesclasstitle").map(|t| t.to_owned());
+
+        let event_date = find(root, |t| {
+            t.contains("eventdate") || t.contains("dateofevent")
+        })
+        .map(iso_date);
+
+        let mut owners = Vec::new();
+
+        for i in root
+            .descendants()
+            .filter(|n| {
+                n.tag_name()
+                    .name()
+                    .eq_ignore_ascii_case("reportingPersonName")
+            })
+            .filter_map(|n| n.parent_element())
+        {
+            owners.push(BeneficialOwner {
+                name: find(i, |t| t == "reportingpersonname")
+                    .ok_or(anyhow!("No reporting person name found"))?
+                    .to_owned(),
+                cik: find(i, |t| t == "reportingpersoncik").map(|t| t.to_owned()),
+                shares: find(i, |t| t.contains("aggregate"))
+                    .and_then(|t| t.replace(',', "").parse().ok()),
+                percent: find(i, |t| {
+                    t.contains("percentofclass") || t.contains("classpercent")
+                })
+                .and_then(|t| t.replace(['%', ','], "").trim().parse().ok()),
+            });
+        }
+
+        Ok(Self {
+            id,
+            file_name,
+            form_type,
+            filed,
+            issuer_cik,
+            issuer_name,
+            class_title,
+            event_date,
+            owners,
+        })
+    }
+}
+
+fn find<'a, 'input>(parrent: Node<'a, 'input>, tag: impl Fn(&str) -> bool) -> Option<&'a str> {
+    parrent
+        .descendants()
+        .filter(|n| n.is_element())
+        .find(|n| tag(&n.tag_name().name().to_ascii_lowercase()))?
+        .text()
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::dec;
+
+    use super::*;
+
+    const SCHEDULE13D: &str = include_str!("../tests/fixtures/schedule13d.txt");
+
+    #[test]
+    fn parses_cover_page() {
+        let schedule = Schedule13::new(SCHEDULE13D.to_owned()).unwrap();
+
+        assert_eq!(schedule.id, "0000921895-25-000812");
+        assert_eq!(schedule.form_type, "SCHEDULE 13D");
+        assert_eq!(schedule.issuer_cik, "0001654321");
+        assert_eq!(schedule.event_date.as_deref(), Some("2025-03-14"));
+        assert_eq!(schedule.owners.len(), 2);
+
+        let fund = &schedule.owners[0];
+        assert_eq!(fund.name, "Activist Capital Master Fund LP");
+        assert_eq!(fund.cik.as_deref(), Some("0001876543"));
+        assert_eq!(fund.shares, Some(dec!(6250000)));
+        assert_eq!(fund.percent, Some(dec!(6.4)));
+    }
+}
@@ -0,0 +1,79 @@
+use regex::Regex;
+use roxmltree::Node;
+
+// The parts of an SGML submission (<accession>.txt) the form parsers start from
+pub struct Submission<'a> {
+    pub accession: &'a str,
+    pub form_type: Option<&'a str>,
+    pub file_name: &'a str,
+    pub xml: &'a str,
+    pub filed: Option<String>,
+}
+
+impl<'a> Submission<'a> {
+    // None when the submission has no xml document
+    pub fn parse(data: &'a str) -> Option<Self> {
+        let caps = Regex::new(
+            r"(?s)ACCESSION NUMBER:\s+([a-zA-Z0-9-]*).*?<FILENAME>([a-zA-Z0-9-_]*\.xml).*?<XML>\n(.*?)\n<\/XML>",
+        )
+        .unwrap()
+        .captures(data)?;
+        let form_type = Regex::new(r"CONFORMED SUBMISSION TYPE:\s+([^\n]*)")
+            .unwrap()
+            .captures(data)
+            .and_then(|c| c.get(1))
+            .map(|m| m.as_str().trim());
+        Some(Self {
+            accession: caps.get(1)?.as_str(),
+            form_type,
+            file_name: caps.get(2)?.as_str(),
+            xml: caps.get(3)?.as_str(),
+            filed: filed_as_of(data),
+        })
+    }
+}
+
+// The acceptance time moves to the next day after 17:30, so the filing date always comes
+// from the SGML headers, of a full submission or of the -index-headers.html page.
+pub fn filed_as_of(headers: &str) -> Option<String> {
+    Regex::new(r"FILED AS OF DATE:\s+(\d{4})(\d{2})(\d{2})")
+        .unwrap()
+        .captures(headers)
+        .map(|c| format!("{}-{}-{}", &c[1], &c[2], &c[3]))
+}
+
+// The forms use MM/DD/YYYY, everything else we store uses YYYY-MM-DD
+pub fn iso_date(date: &str) -> String {
+    match date.split('/').collect::<Vec<_>>()[..] {
+        [month, day, year] => format!("{}-{}-{}", year, month, day),
+        _ => date.to_owned(),
+    }
+}
+
+pub fn text<'a, 'input>(parrent: Node<'a, 'input>, tag: &str) -> Option<&'a str> {
+    parrent
+        .children()
+        .find(|n| n.has_tag_name(tag))?
+        .text()
+        .map(|t| t.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_submission() {
+        let data = include_str!("../tests/fixtures/form4_amendment.txt");
+        let submission = Submission::parse(data).unwrap();
+        assert_eq!(submission.accession, "0001209191-24-032007");
+        assert_eq!(submission.form_type, Some("4/A"));
+        assert_eq!(submission.file_name, "doc4a.xml");
+        assert!(submission.xml.starts_with("<?xml"));
+        assert_eq!(submission.filed.as_deref(), Some("2024-03-06"));
+
+        assert!(Submission::parse("ACCESSION NUMBER:\t0001209191-24-032007\n").is_none());
+        assert_eq!(iso_date("03/01/2024"), "2024-03-01");
+        assert_eq!(iso_date("2024-03-01"), "2024-03-01");
+    }
+}
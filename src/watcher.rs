@@ -1,127 +1,108 @@
 use chrono::{DateTime, NaiveDateTime};
-use reqwest::{Client, Method};
 use roxmltree::{Document, ParsingOptions};
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
 use tokio::{
-    sync::{Mutex, oneshot, watch},
+    sync::{Mutex, mpsc, oneshot},
     time::interval,
 };
 
+use crate::{
+    db::{Db, Storage},
+    http::Http,
+    index,
+};
+
+const PAGE_SIZE: usize = 100;
+const SEEN_CAPACITY: usize = 10_000;
+
 pub struct Watcher {
     url: String,
+    forms: Vec<String>,
+    pages: usize,
     interval: Duration,
+    db: Db,
+    http: Http,
     stop_tx: Option<oneshot::Sender<()>>,
-    change_rx: watch::Receiver<Option<Vec<String>>>,
+    change_rx: mpsc::UnboundedReceiver<Vec<String>>,
     last_change: Arc<Mutex<NaiveDateTime>>,
 }
 
 impl Watcher {
-    pub fn new(url: String, interval: Duration) -> Self {
+    pub fn new(
+        url: String,
+        forms: &[&str],
+        pages: usize,
+        interval: Duration,
+        db: Db,
+        http: Http,
+    ) -> Self {
         let (stop_tx, stop_rx) = oneshot::channel();
-        let (change_tx, change_rx) = watch::channel::<Option<Vec<String>>>(None);
+        let (change_tx, change_rx) = mpsc::unbounded_channel();
 
         let watcher = Self {
-            url: url,
-            interval: interval,
+            url,
+            forms: forms.iter().map(|f| format!("{} ", f)).collect(),
+            pages,
+            interval,
+            db,
+            http,
             stop_tx: Some(stop_tx),
-            change_rx: change_rx,
+            change_rx,
             last_change: Arc::new(Mutex::new(DateTime::UNIX_EPOCH.naive_local())),
         };
         watcher.start(stop_rx, change_tx);
         watcher
     }
 
-    fn start(&self, stop_rx: oneshot::Receiver<()>, change_tx: watch::Sender<Option<Vec<String>>>) {
+    fn start(&self, stop_rx: oneshot::Receiver<()>, change_tx: mpsc::UnboundedSender<Vec<String>>) {
         let url = self.url.clone();
+        let forms = self.forms.clone();
+        let pages = self.pages;
         let mut interval = interval(self.interval);
         let last_time = self.last_change.clone();
+        let db = self.db.clone();
+        let http = self.http.clone();
+        // Keyed by form types rather than url so the cursor survives feed url changes
+        let feed = forms.join(",");
         tokio::spawn(async move {
             let query = tokio::spawn(async move {
+                let mut seen = Seen::default();
+                if let Some(cursor) = db.cursor(&feed).await {
+                    *last_time.lock().await = cursor;
+                    let mut out = Vec::new();
+                    for link in index::since(&http, cursor.date(), &forms).await {
+                        if seen.is_new(db.as_ref(), &link).await {
+                            out.push(link);
+                        }
+                    }
+                    let _ = change_tx.send(out);
+                }
                 loop {
                     interval.tick().await;
-                    let client = Client::new();
-                    let req = client
-                        .request(Method::GET, &url)
-                        .header("User-Agent", "some@email.com");
-                    let Ok(req) = req.build() else {
-                        continue;
-                    };
-                    let Ok(res) = client.execute(req).await else {
-                        continue;
-                    };
-                    let Ok(data) = res.text().await else {
-                        continue;
-                    };
-                    let opt = ParsingOptions {
-                        ..Default::default()
-                    };
-                    let Ok(document) = Document::parse_with_options(&data, opt) else {
-                        continue;
-                    };
-                    let entries: Vec<_> = document
-                        .descendants()
-                        .filter(|n| n.has_tag_name(("http://www.w3.org/2005/Atom", "entry")))
-                        .collect();
-                    let mut out = Vec::new();
-                    let mut peekable = entries.iter().peekable();
-                    let Some(first) = peekable.peek() else {
-                        continue;
-                    };
-                    let Some(updated) = first
-                        .children()
-                        .find(|n| n.has_tag_name(("http://www.w3.org/2005/Atom", "updated")))
-                    else {
-                        continue;
-                    };
-                    let Some(updated) = updated.text() else {
-                        continue;
-                    };
-                    let Ok(updated) = DateTime::parse_from_rfc3339(updated) else {
+                    let last = *last_time.lock().await;
+                    let poll = Poll::run(pages, last, &forms, &mut seen, db.as_ref(), |n| {
+                        let url = format!("{}&start={}&count={}", url, n * PAGE_SIZE, PAGE_SIZE);
+                        let http = http.clone();
+                        async move { page(&http, &url).await }
+                    })
+                    .await;
+                    let Some(newest) = poll.newest else {
                         continue;
                     };
-                    for i in entries {
-                        match i
-                            .children()
-                            .find(|n| n.has_tag_name(("http://www.w3.org/2005/Atom", "title")))
-                        {
-                            Some(t) => {
-                                let Some(t) = t.text() else {
-                                    continue;
-                                };
-                                if !t.starts_with("4 ") {
-                                    continue;
-                                }
-                            }
-                            None => continue,
-                        }
-                        let Some(time) = i
-                            .children()
-                            .find(|n| n.has_tag_name(("http://www.w3.org/2005/Atom", "updated")))
-                        else {
-                            continue;
-                        };
-                        let Some(time) = time.text() else {
-                            continue;
-                        };
-                        let Ok(time) = DateTime::parse_from_rfc3339(time) else {
-                            continue;
-                        };
-                        if time.naive_local() <= *last_time.lock().await {
-                            continue;
-                        }
-                        let Some(link) = i
-                            .children()
-                            .find(|n| n.has_tag_name(("http://www.w3.org/2005/Atom", "link")))
-                        else {
-                            continue;
-                        };
-                        let Some(link) = link.attribute("href") else {
-                            continue;
-                        };
-                        out.push(link.replace("-index.htm", ".txt"));
+                    if poll.incomplete(last) {
+                        eprintln!(
+                            "Feed {} had more than {} pages of new entries, coverage since {} is incomplete.",
+                            feed, pages, last
+                        );
+                        db.record_incomplete(&feed, last, poll.oldest).await;
                     }
-                    *last_time.lock().await = updated.naive_local();
-                    let _ = change_tx.send(Some(out));
+                    *last_time.lock().await = newest;
+                    db.set_cursor(&feed, newest).await;
+                    let _ = change_tx.send(poll.links);
                 }
             });
             let stop = tokio::spawn(stop_rx);
@@ -132,11 +113,8 @@ impl Watcher {
         });
     }
 
-    pub async fn wait(
-        &mut self,
-    ) -> Result<Option<Vec<String>>, tokio::sync::watch::error::RecvError> {
-        self.change_rx.changed().await?;
-        Ok(self.change_rx.borrow().clone())
+    pub async fn wait(&mut self) -> Option<Vec<String>> {
+        self.change_rx.recv().await
     }
 }
 
@@ -145,3 +123,316 @@ impl Drop for Watcher {
         let _ = self.stop_tx.take().unwrap().send(());
     }
 }
+
+#[derive(Default)]
+struct Seen {
+    set: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl Seen {
+    async fn is_new(&mut self, db: &dyn Storage, link: &str) -> bool {
+        let id = index::accession(link);
+        if self.set.contains(&id) || db.known(&id).await {
+            return false;
+        }
+        self.remember(id);
+        true
+    }
+
+    // The oldest accessions are forgotten first, by then they are stored or quarantined
+    fn remember(&mut self, id: String) {
+        if self.order.len() >= SEEN_CAPACITY
+            && let Some(old) = self.order.pop_front()
+        {
+            self.set.remove(&old);
+        }
+        self.set.insert(id.clone());
+        self.order.push_back(id);
+    }
+}
+
+// What one poll of the feed found, fetching the pages is left to the caller
+#[derive(Default)]
+struct Poll {
+    links: Vec<String>,
+    newest: Option<NaiveDateTime>,
+    oldest: Option<NaiveDateTime>,
+    complete: bool,
+}
+
+impl Poll {
+    async fn run<F>(
+        pages: usize,
+        last: NaiveDateTime,
+        forms: &[String],
+        seen: &mut Seen,
+        db: &dyn Storage,
+        mut fetch: impl FnMut(usize) -> F,
+    ) -> Self
+    where
+        F: Future<Output = Option<Vec<Entry>>>,
+    {
+        let mut poll = Self::default();
+        // Without a cursor there is nothing to catch up on, the newest page is where it starts
+        let pages = if last == DateTime::UNIX_EPOCH.naive_local() {
+            1
+        } else {
+            pages
+        };
+        for n in 0..pages {
+            let Some(entries) = fetch(n).await else {
+                break;
+            };
+            if n == 0 {
+                poll.newest = entries.first().map(|e| e.updated);
+            }
+            for i in &entries {
+                poll.oldest = Some(i.updated);
+                // The cursor only decides how far to page, novelty is decided by accession number
+                if i.updated < last {
+                    poll.complete = true;
+                }
+                if !forms.iter().any(|f| i.title.starts_with(f)) {
+                    continue;
+                }
+                let link = i.link.replace("-index.htm", ".txt");
+                if seen.is_new(db, &link).await {
+                    poll.links.push(link);
+                }
+            }
+            // A short page is the end of the feed
+            if entries.len() < PAGE_SIZE {
+                poll.complete = true;
+            }
+            if poll.complete {
+                break;
+            }
+        }
+        poll
+    }
+
+    // The page cap was hit before the cursor, entries between the two were never seen
+    fn incomplete(&self, last: NaiveDateTime) -> bool {
+        self.newest.is_some() && !self.complete && last != DateTime::UNIX_EPOCH.naive_local()
+    }
+}
+
+struct Entry {
+    title: String,
+    updated: NaiveDateTime,
+    link: String,
+}
+
+async fn page(http: &Http, url: &str) -> Option<Vec<Entry>> {
+    entries(&http.get(url).await.ok()?)
+}
+
+fn entries(data: &str) -> Option<Vec<Entry>> {
+    let opt = ParsingOptions {
+        ..Default::default()
+    };
+    let document = Document::parse_with_options(data, opt).ok()?;
+    Some(
+        document
+            .descendants()
+            .filter(|n| n.has_tag_name(("http://www.w3.org/2005/Atom", "entry")))
+            .filter_map(|i| {
+                let title = i
+                    .children()
+                    .find(|n| n.has_tag_name(("http://www.w3.org/2005/Atom", "title")))?
+                    .text()?
+                    .to_owned();
+                let updated = i
+                    .children()
+                    .find(|n| n.has_tag_name(("http://www.w3.org/2005/Atom", "updated")))?
+                    .text()?;
+                let updated = DateTime::parse_from_rfc3339(updated).ok()?.naive_local();
+                let link = i
+                    .children()
+                    .find(|n| n.has_tag_name(("http://www.w3.org/2005/Atom", "link")))?
+                    .attribute("href")?
+                    .to_owned();
+                Some(Entry {
+                    title,
+                    updated,
+                    link,
+                })
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::{db, file4::File4};
+
+    // One entry of the getcurrent Atom feed, EDGAR writes its times in Eastern time
+    fn entry(form: &str, n: usize, updated: NaiveDateTime) -> String {
+        format!(
+            r#"<entry><title>{} - Doe Jane (0001771234) (Reporting)</title><link rel="alternate" type="text/html" href="https://www.sec.gov/Archives/edgar/data/1771234/000120919124{:06}/0001209191-24-{:06}-index.htm"/><summary type="html">Filed: 2024-03-05</summary><updated>{}-05:00</updated><category scheme="https://www.sec.gov/" label="form type" term="{}"/><id>urn:tag:sec.gov,2008:accession-number=0001209191-24-{:06}</id></entry>"#,
+            form,
+            n,
+            n,
+            updated.format("%Y-%m-%dT%H:%M:%S"),
+            form,
+            n
+        )
+    }
+
+    // Pages of Form 4s a minute apart, newest first, numbered from the newest
+    fn feed(pages: &[usize], newest: NaiveDateTime) -> Vec<String> {
+        let mut n = 0;
+        pages
+            .iter()
+            .map(|len| {
+                let mut data = String::from(
+                    r#"<?xml version="1.0" encoding="ISO-8859-1" ?><feed xmlns="http://www.w3.org/2005/Atom"><title>Latest Filings</title>"#,
+                );
+                for _ in 0..*len {
+                    data.push_str(&entry("4", n, newest - TimeDelta::minutes(n as i64)));
+                    n += 1;
+                }
+                data.push_str("</feed>");
+                data
+            })
+            .collect()
+    }
+
+    async fn poll(
+        feed: &[String],
+        pages: usize,
+        last: NaiveDateTime,
+        seen: &mut Seen,
+        db: &dyn Storage,
+    ) -> (Poll, Vec<usize>) {
+        let mut fetched = Vec::new();
+        let poll = Poll::run(pages, last, &["4 ".to_owned()], seen, db, |n| {
+            fetched.push(n);
+            let page = feed.get(n).and_then(|p| entries(p));
+            async move { page }
+        })
+        .await;
+        (poll, fetched)
+    }
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[tokio::test]
+    async fn pages_until_the_cursor() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = db::connect(&format!(
+            "sqlite://{}",
+            dir.path().join("db.sqlite").display()
+        ))
+        .await;
+        let newest = time("2024-03-05 18:30:00");
+        let epoch = DateTime::UNIX_EPOCH.naive_local();
+
+        // The first poll has no cursor to catch up to, it only reads the newest page
+        let full = feed(&[PAGE_SIZE, PAGE_SIZE, PAGE_SIZE], newest);
+        let (first, fetched) = poll(&full, 5, epoch, &mut Seen::default(), db.as_ref()).await;
+        assert_eq!(fetched, [0]);
+        assert_eq!(first.newest, Some(newest));
+        assert_eq!(first.links.len(), PAGE_SIZE);
+        assert_eq!(
+            first.links[0],
+            "https://www.sec.gov/Archives/edgar/data/1771234/000120919124000000/0001209191-24-000000.txt"
+        );
+        assert!(!first.incomplete(epoch));
+
+        // The cursor sits on the second page, the third is never fetched
+        let last = newest - TimeDelta::minutes(150);
+        let (reached, fetched) = poll(&full, 5, last, &mut Seen::default(), db.as_ref()).await;
+        assert_eq!(fetched, [0, 1]);
+        assert!(reached.complete && !reached.incomplete(last));
+        // Entries past the cursor are still offered, known ones are filtered by accession
+        assert_eq!(reached.links.len(), 2 * PAGE_SIZE);
+
+        // A short page is the end of the feed
+        let short = feed(&[PAGE_SIZE, 3], newest);
+        let last = newest - TimeDelta::days(1);
+        let (end, fetched) = poll(&short, 5, last, &mut Seen::default(), db.as_ref()).await;
+        assert_eq!(fetched, [0, 1]);
+        assert!(!end.incomplete(last));
+
+        // The page cap stops paging before the cursor, that gap is recorded
+        let (capped, fetched) = poll(&full, 2, last, &mut Seen::default(), db.as_ref()).await;
+        assert_eq!(fetched, [0, 1]);
+        assert!(capped.incomplete(last));
+        assert_eq!(
+            capped.oldest,
+            Some(newest - TimeDelta::minutes(2 * PAGE_SIZE as i64 - 1))
+        );
+
+        // A failed fetch ends the poll without claiming coverage either way
+        let (failed, fetched) = poll(&[], 5, last, &mut Seen::default(), db.as_ref()).await;
+        assert_eq!(fetched, [0]);
+        assert_eq!(failed.newest, None);
+        assert!(!failed.incomplete(last));
+    }
+
+    #[tokio::test]
+    async fn skips_seen_and_stored_filings() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = db::connect(&format!(
+            "sqlite://{}",
+            dir.path().join("db.sqlite").display()
+        ))
+        .await;
+        let stored =
+            File4::new(include_str!("../tests/fixtures/form4_derivative.txt").to_owned()).unwrap();
+        assert!(db.insert_file4(&stored).await);
+
+        let newest = time("2024-03-05 18:30:00");
+        let mut data = String::from(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+        data.push_str(&entry("4", 31842, newest));
+        data.push_str(&entry("144", 1, newest));
+        data.push_str(&entry("4", 2, newest));
+        data.push_str("</feed>");
+        let mut seen = Seen::default();
+        let (first, _) = poll(
+            &[data.clone()],
+            5,
+            time("2024-03-05 00:00:00"),
+            &mut seen,
+            db.as_ref(),
+        )
+        .await;
+        // The stored filing and the other form are left out
+        assert_eq!(
+            first.links,
+            [
+                "https://www.sec.gov/Archives/edgar/data/1771234/000120919124000002/0001209191-24-000002.txt"
+            ]
+        );
+        // Polled again before it was stored, it is not handed out twice
+        let (again, _) = poll(
+            &[data],
+            5,
+            time("2024-03-05 00:00:00"),
+            &mut seen,
+            db.as_ref(),
+        )
+        .await;
+        assert!(again.links.is_empty());
+    }
+
+    #[test]
+    fn bounds_seen() {
+        let mut seen = Seen::default();
+        for n in 0..=SEEN_CAPACITY {
+            seen.remember(n.to_string());
+        }
+        assert_eq!(seen.set.len(), SEEN_CAPACITY);
+        assert_eq!(seen.order.len(), SEEN_CAPACITY);
+        assert!(!seen.set.contains("0"));
+        assert!(seen.set.contains("1"));
+        assert!(seen.set.contains(&SEEN_CAPACITY.to_string()));
+    }
+}